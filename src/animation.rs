@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec4};
+
+use crate::scene::{NodeId, Position, Scene};
+
+pub type AnimationId = u32;
+
+pub(crate) type CompletionCallback = Box<dyn FnOnce(&mut Scene)>;
+
+/// Easing curve applied to the normalized animation time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress `t` in 0~1 to eased progress in 0~1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// Polyline parametrized by arc length, so that equal time steps cover equal distances.
+#[derive(Clone, Debug)]
+pub(crate) struct Path {
+    points: Vec<Vec2>,
+    // cumulative length at each point. lengths[0] is always 0.
+    lengths: Vec<f32>,
+}
+
+impl Path {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        let mut lengths = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                total += point.distance(points[i - 1]);
+            }
+            lengths.push(total);
+        }
+        Self { points, lengths }
+    }
+
+    /// Point at the given fraction (0~1) of the total length.
+    pub fn sample(&self, t: f32) -> Option<Vec2> {
+        let total = *self.lengths.last()?;
+        if total <= 0.0 {
+            return self.points.first().copied();
+        }
+
+        let target = t.clamp(0.0, 1.0) * total;
+        let end = self
+            .lengths
+            .partition_point(|length| *length < target)
+            .clamp(1, self.points.len() - 1);
+        let start = end - 1;
+        let segment = self.lengths[end] - self.lengths[start];
+        let ratio = if segment > 0.0 {
+            (target - self.lengths[start]) / segment
+        } else {
+            0.0
+        };
+        Some(self.points[start].lerp(self.points[end], ratio))
+    }
+}
+
+pub(crate) enum AnimationKind {
+    Path(Path),
+    // tween between positions checked to be of the same kinds
+    Position {
+        from: Box<Position>,
+        to: Box<Position>,
+    },
+    Color {
+        from: Vec4,
+        to: Vec4,
+    },
+}
+
+pub(crate) struct Animation {
+    pub node_id: NodeId,
+    pub kind: AnimationKind,
+    pub duration: f32,
+    pub elapsed: f32,
+    pub easing: Easing,
+    pub looping: bool,
+    pub on_complete: Option<CompletionCallback>,
+}
+
+impl Animation {
+    pub fn new(node_id: NodeId, kind: AnimationKind, duration: f32, easing: Easing) -> Self {
+        Self {
+            node_id,
+            kind,
+            duration,
+            elapsed: 0.0,
+            easing,
+            looping: false,
+            on_complete: None,
+        }
+    }
+
+    // Eased progress of the animation in 0~1.
+    fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            self.easing.apply(self.elapsed / self.duration)
+        }
+    }
+}
+
+/// Frame update produced by advancing an animation.
+pub(crate) enum AnimationUpdate {
+    Offset(NodeId, Vec2),
+    Position(NodeId, Position),
+    Color(NodeId, Vec4),
+}
+
+#[derive(Default)]
+pub(crate) struct Animator {
+    last_id: AnimationId,
+    animations: HashMap<AnimationId, Animation>,
+}
+
+impl Animator {
+    pub fn insert(&mut self, animation: Animation) -> AnimationId {
+        let id = self.last_id;
+        self.last_id += 1;
+        self.animations.entry(id).insert_entry(animation);
+        id
+    }
+
+    pub fn get_mut(&mut self, id: AnimationId) -> Option<&mut Animation> {
+        self.animations.get_mut(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Advance every animation by `dt` seconds.
+    /// Returns updates to apply to the scene and completion callbacks of finished animations.
+    pub fn advance(&mut self, dt: f32) -> (Vec<AnimationUpdate>, Vec<CompletionCallback>) {
+        let mut updates = Vec::new();
+        let mut finished = Vec::new();
+
+        for (id, animation) in self.animations.iter_mut() {
+            animation.elapsed += dt;
+            if animation.elapsed >= animation.duration {
+                if animation.looping && animation.duration > 0.0 {
+                    animation.elapsed %= animation.duration;
+                } else {
+                    animation.elapsed = animation.duration;
+                    finished.push(*id);
+                }
+            }
+
+            let progress = animation.progress();
+            match &animation.kind {
+                AnimationKind::Path(path) => {
+                    if let Some(point) = path.sample(progress) {
+                        updates.push(AnimationUpdate::Offset(animation.node_id, point));
+                    }
+                }
+                AnimationKind::Position { from, to } => {
+                    if let Some(position) = from.lerp(to, progress) {
+                        updates.push(AnimationUpdate::Position(animation.node_id, position));
+                    }
+                }
+                AnimationKind::Color { from, to } => {
+                    updates.push(AnimationUpdate::Color(
+                        animation.node_id,
+                        from.lerp(*to, progress),
+                    ));
+                }
+            }
+        }
+
+        let callbacks = finished
+            .into_iter()
+            .filter_map(|id| self.animations.remove(&id)?.on_complete)
+            .collect();
+
+        (updates, callbacks)
+    }
+}
+
+impl std::fmt::Debug for Animator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Animator")
+            .field("last_id", &self.last_id)
+            .field("running", &self.animations.len())
+            .finish()
+    }
+}
@@ -0,0 +1,73 @@
+//! Color constructors for the linear RGBA `Vec4` colors taken by the nodes.
+//!
+//! Node colors are linear, and the sRGB surface encodes them on write, so a gray of `0.5` is drawn as the sRGB
+//! value 188, not as the perceptual mid-gray 128. Colors picked in design tools and written as hex codes are
+//! sRGB-encoded, so these helpers decode the RGB channels to linear.
+//! Alpha is always linear. To give node colors sRGB-encoded instead, see [ColorSpace].
+
+use glam::Vec4;
+
+use crate::draw;
+
+/// How the RGB channels of node colors, tints and the background are interpreted, set with
+/// [crate::Scene::set_color_space]. Alpha is always linear.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorSpace {
+    /// Linear, as the helpers of this module return: blending and gradients work on them as given.
+    #[default]
+    Linear,
+    /// sRGB-encoded, like hex codes: `0.5` is the perceptual mid-gray. Colors are decoded to linear before drawing,
+    /// so blending and gradients still work in linear space.
+    Srgb,
+}
+
+/// Color from sRGB-encoded 8-bit channels, e.g. `rgba8(255, 128, 0, 255)` for opaque orange.
+pub fn rgba8(r: u8, g: u8, b: u8, a: u8) -> Vec4 {
+    let decode = |c: u8| draw::srgb_decode(c as f32 / 255.0);
+    Vec4::new(decode(r), decode(g), decode(b), a as f32 / 255.0)
+}
+
+/// Color from an sRGB-encoded `0xRRGGBBAA` hex code, e.g. `hex(0xff8000ff)` for opaque orange.
+pub fn hex(rgba: u32) -> Vec4 {
+    let [r, g, b, a] = rgba.to_be_bytes();
+    rgba8(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Guiug, types::Dimension};
+
+    #[test]
+    fn srgb_color_space_draws_half_gray_as_mid_gray() {
+        let gray = Vec4::new(0.5, 0.5, 0.5, 1.0);
+        let mut guiug = Guiug::default();
+        let rect = guiug.rect_node(gray);
+        guiug.set_root(rect).unwrap();
+        let scene = guiug.scene_mut();
+        scene.set_background(gray);
+
+        // the rect's color and the background, as drawn in linear space
+        let drawn = |scene: &crate::Scene| {
+            let draw_list = draw::lower(scene, Dimension::new(4, 4));
+            let [draw::DrawCommand::FilledRect { color, .. }] = draw_list.commands.as_slice()
+            else {
+                panic!("expected one rect, got {:?}", draw_list.commands);
+            };
+            (*color, scene.background())
+        };
+        // the 8-bit value the sRGB surface stores for a linear channel
+        let stored = |c: f32| (draw::srgb_encode(c) * 255.0).round() as u8;
+
+        assert_eq!(drawn(scene), (gray, gray));
+        assert_eq!(stored(gray.x), 188);
+
+        scene.set_color_space(ColorSpace::Srgb);
+        let (color, background) = drawn(scene);
+        assert_eq!(color, background);
+        assert!((color.x - 0.214).abs() < 1e-3, "decoded to {color}");
+        assert_eq!((color.w, background.w), (1.0, 1.0));
+        assert_eq!(stored(color.x), 128);
+    }
+}
@@ -0,0 +1,414 @@
+//! Software rasterizer for machines without any GPU, drawing the laid out scene with tiny-skia.
+//! Colors are blended in linear space like the sRGB surface does. Shader effects are not reproduced, and shadows are
+//! stacked translucent rects.
+
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use tiny_skia::{
+    Color, ColorSpace, FillRule, FilterQuality, GradientStop, IntSize, LineJoin, LinearGradient,
+    Mask, Paint, Path, PathBuilder, Pattern, Pixmap, Point, SpreadMode, Stroke, Transform,
+};
+
+use crate::{
+    draw::{self, DrawCommand},
+    scene::{BlendMode, Scene},
+    texture::{self, Filter, TextureId, TextureInfo, TextureInfoManager},
+    types::{Dimension, Rect},
+};
+
+pub(crate) fn render(
+    scene: &Scene,
+    texture_infos: Option<&TextureInfoManager>,
+    screen_size: Dimension,
+) -> RgbaImage {
+    let width = screen_size.width.max(0) as u32;
+    let height = screen_size.height.max(0) as u32;
+    let Some(mut pixmap) = Pixmap::new(width, height) else {
+        return RgbaImage::new(width, height);
+    };
+
+    let screen = Rect::new(0, 0, screen_size.width, screen_size.height);
+    fill(
+        &mut pixmap,
+        screen,
+        screen,
+        Transform::identity(),
+        paint(scene.background(), tiny_skia::BlendMode::SourceOver),
+    );
+
+    let draw_list = draw::lower(scene, screen_size);
+    let mut painter = Painter {
+        scene,
+        texture_infos,
+        groups: &draw_list.groups,
+        screen,
+        textures: HashMap::new(),
+    };
+    painter.draw(&mut pixmap, &draw_list.commands);
+
+    RgbaImage::from_raw(width, height, pixmap.take_demultiplied())
+        .unwrap_or_else(|| RgbaImage::new(width, height))
+}
+
+/// Draws lists of commands, caching the decoded textures.
+struct Painter<'a> {
+    scene: &'a Scene,
+    texture_infos: Option<&'a TextureInfoManager<'a>>,
+    /// Draw lists of the opacity groups of the scene.
+    groups: &'a [draw::DrawList],
+    screen: Rect,
+    /// Decoded textures by id and tint bits, `None` if decoding failed.
+    textures: HashMap<(TextureId, [u32; 4]), Option<Pixmap>>,
+}
+
+impl Painter<'_> {
+    fn draw(&mut self, pixmap: &mut Pixmap, commands: &[DrawCommand]) {
+        let mut clip = self.screen;
+        let mut transform = Transform::identity();
+        let mut blend = tiny_skia::BlendMode::SourceOver;
+        for command in commands {
+            match *command {
+                // translucent pixels of Replace are alpha blended too, which source-over does for every pixel
+                DrawCommand::Blend(mode) => {
+                    blend = match mode {
+                        BlendMode::Replace | BlendMode::Alpha => tiny_skia::BlendMode::SourceOver,
+                        BlendMode::Additive => tiny_skia::BlendMode::Plus,
+                        BlendMode::Multiply => tiny_skia::BlendMode::Multiply,
+                    }
+                }
+                DrawCommand::Clip(rect) => {
+                    clip = rect.map_or(self.screen, |rect| self.screen.intersect(rect))
+                }
+                DrawCommand::Transform(affine) => {
+                    transform = affine.map_or(Transform::identity(), |affine| {
+                        let [a, b, c, d] = affine.matrix2.to_cols_array();
+                        Transform::from_row(a, b, c, d, affine.translation.x, affine.translation.y)
+                    })
+                }
+                DrawCommand::FilledRect {
+                    rect,
+                    color,
+                    radius: 0,
+                    border: None,
+                    ..
+                } => {
+                    fill(pixmap, rect, clip, transform, paint(color, blend));
+                }
+                DrawCommand::FilledRect {
+                    rect,
+                    color,
+                    radius,
+                    border,
+                    ..
+                } => {
+                    let (fill_rect, fill_radius) = match border {
+                        Some(border) => border.inner(rect, radius),
+                        None => (rect, radius),
+                    };
+                    let mut path = PathBuilder::new();
+                    push_rounded_rect(&mut path, fill_rect, fill_radius as f32);
+                    if let Some(path) = path.finish() {
+                        fill_path(
+                            pixmap,
+                            &path,
+                            FillRule::Winding,
+                            clip,
+                            transform,
+                            paint(color, blend),
+                        );
+                    }
+                    // the border is the ring between the outer and the inner outline
+                    if let Some(border) = border {
+                        let mut path = PathBuilder::new();
+                        push_rounded_rect(&mut path, rect, radius as f32);
+                        push_rounded_rect(&mut path, fill_rect, fill_radius as f32);
+                        if let Some(path) = path.finish() {
+                            fill_path(
+                                pixmap,
+                                &path,
+                                FillRule::EvenOdd,
+                                clip,
+                                transform,
+                                paint(border.color, blend),
+                            );
+                        }
+                    }
+                }
+                DrawCommand::GradientRect {
+                    rect,
+                    start,
+                    end,
+                    angle,
+                    ..
+                } => {
+                    // the gradient is laid out in the unit square and mapped onto the rect, like the renderer does
+                    let vector = draw::gradient_vector(angle);
+                    let Some(shader) = LinearGradient::new(
+                        Point::from_xy(0.5 - vector.x / 2.0, 0.5 - vector.y / 2.0),
+                        Point::from_xy(0.5 + vector.x / 2.0, 0.5 + vector.y / 2.0),
+                        vec![
+                            GradientStop::new(0.0, color(start)),
+                            GradientStop::new(1.0, color(end)),
+                        ],
+                        SpreadMode::Pad,
+                        Transform::from_row(
+                            rect.w as f32,
+                            0.0,
+                            0.0,
+                            rect.h as f32,
+                            rect.x as f32,
+                            rect.y as f32,
+                        ),
+                    ) else {
+                        continue;
+                    };
+                    let paint = Paint {
+                        shader,
+                        colorspace: ColorSpace::FullSRGBGamma,
+                        blend_mode: blend,
+                        ..Default::default()
+                    };
+                    fill(pixmap, rect, clip, transform, paint);
+                }
+                // the outline of the stroke is filled, so that overlapping segments are covered once
+                DrawCommand::Polyline {
+                    ref points,
+                    width,
+                    color,
+                    ..
+                } => {
+                    let mut path = PathBuilder::new();
+                    path.move_to(points[0].x, points[0].y);
+                    for point in &points[1..] {
+                        path.line_to(point.x, point.y);
+                    }
+                    let stroke = Stroke {
+                        width,
+                        line_join: LineJoin::Bevel,
+                        ..Default::default()
+                    };
+                    if let Some(outline) = path.finish().and_then(|path| path.stroke(&stroke, 1.0))
+                    {
+                        fill_path(
+                            pixmap,
+                            &outline,
+                            FillRule::Winding,
+                            clip,
+                            transform,
+                            paint(color, blend),
+                        );
+                    }
+                }
+                DrawCommand::Checkerboard {
+                    rect, color, cell, ..
+                } => {
+                    for cell in draw::checkerboard_cells(rect, cell) {
+                        fill(pixmap, cell, clip, transform, paint(color, blend));
+                    }
+                }
+                DrawCommand::Shadow {
+                    rect, color, blur, ..
+                } => {
+                    for (layer, color) in draw::shadow_layers(rect, blur, color) {
+                        fill(pixmap, layer, clip, transform, paint(color, blend));
+                    }
+                }
+                DrawCommand::FilledEllipse { rect, color, .. } => {
+                    if let Some(oval) = tiny_skia::Rect::from_xywh(
+                        rect.x as f32,
+                        rect.y as f32,
+                        rect.w as f32,
+                        rect.h as f32,
+                    ) && let Some(path) = PathBuilder::from_oval(oval)
+                    {
+                        fill_path(
+                            pixmap,
+                            &path,
+                            FillRule::Winding,
+                            clip,
+                            transform,
+                            paint(color, blend),
+                        );
+                    }
+                }
+                DrawCommand::TexturedQuad {
+                    rect,
+                    texture_id,
+                    tint,
+                    uv_min,
+                    uv_max,
+                    ..
+                } => {
+                    let Some(texture_info) =
+                        texture::lookup(self.scene, self.texture_infos, texture_id)
+                    else {
+                        continue;
+                    };
+                    let Some(texture) = self
+                        .textures
+                        .entry((texture_id, tint.to_array().map(f32::to_bits)))
+                        .or_insert_with(|| decode(texture_info, tint))
+                        .as_ref()
+                    else {
+                        continue;
+                    };
+                    // map the texture region from uv_min to uv_max onto the rect
+                    let texture_size =
+                        glam::Vec2::new(texture.width() as f32, texture.height() as f32);
+                    let scale = glam::Vec2::new(rect.w as f32, rect.h as f32)
+                        / ((uv_max - uv_min) * texture_size);
+                    let translate = glam::Vec2::new(rect.x as f32, rect.y as f32)
+                        - uv_min * texture_size * scale;
+                    let uv_transform =
+                        Transform::from_row(scale.x, 0.0, 0.0, scale.y, translate.x, translate.y);
+                    let paint = Paint {
+                        shader: Pattern::new(
+                            texture.as_ref(),
+                            SpreadMode::Pad,
+                            match texture_info.options.filter {
+                                Filter::Linear => FilterQuality::Bilinear,
+                                Filter::Nearest => FilterQuality::Nearest,
+                            },
+                            1.0,
+                            uv_transform,
+                        ),
+                        colorspace: ColorSpace::FullSRGBGamma,
+                        blend_mode: blend,
+                        ..Default::default()
+                    };
+                    fill(pixmap, rect, clip, transform, paint);
+                }
+                // the group is drawn into its own pixmap, then composited in linear space like the renderer does
+                DrawCommand::Group {
+                    rect, index, alpha, ..
+                } => {
+                    let groups = self.groups;
+                    let (Some(group), Some(mut layer)) = (
+                        groups.get(index),
+                        Pixmap::new(pixmap.width(), pixmap.height()),
+                    ) else {
+                        continue;
+                    };
+                    self.draw(&mut layer, &group.commands);
+                    let paint = Paint {
+                        shader: Pattern::new(
+                            layer.as_ref(),
+                            SpreadMode::Pad,
+                            FilterQuality::Nearest,
+                            alpha.clamp(0.0, 1.0),
+                            Transform::identity(),
+                        ),
+                        colorspace: ColorSpace::FullSRGBGamma,
+                        ..Default::default()
+                    };
+                    fill(pixmap, rect, self.screen, Transform::identity(), paint);
+                }
+            }
+        }
+    }
+}
+
+fn paint(color: glam::Vec4, blend: tiny_skia::BlendMode) -> Paint<'static> {
+    let mut paint = Paint {
+        colorspace: ColorSpace::FullSRGBGamma,
+        blend_mode: blend,
+        ..Default::default()
+    };
+    paint.set_color(self::color(color));
+    paint
+}
+
+// tiny-skia takes sRGB-encoded colors and linearizes them for blending
+fn color(color: glam::Vec4) -> Color {
+    Color::from_rgba8(
+        (draw::srgb_encode(color.x) * 255.0).round() as u8,
+        (draw::srgb_encode(color.y) * 255.0).round() as u8,
+        (draw::srgb_encode(color.z) * 255.0).round() as u8,
+        (color.w.clamp(0.0, 1.0) * 255.0).round() as u8,
+    )
+}
+
+// Fill the rect mapped by `transform`, restricted to the clip rect.
+fn fill(pixmap: &mut Pixmap, rect: Rect, clip: Rect, transform: Transform, paint: Paint) {
+    if !transform.is_identity() {
+        if let Some(rect) =
+            tiny_skia::Rect::from_xywh(rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32)
+        {
+            let path = PathBuilder::from_rect(rect);
+            fill_path(pixmap, &path, FillRule::Winding, clip, transform, paint);
+        }
+        return;
+    }
+    let rect = clip.intersect(rect);
+    if rect.w <= 0 || rect.h <= 0 {
+        return;
+    }
+    let Some(rect) =
+        tiny_skia::Rect::from_xywh(rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32)
+    else {
+        return;
+    };
+    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+}
+
+// Fill an anti-aliased path mapped by `transform`, restricted to the clip rect.
+fn fill_path(
+    pixmap: &mut Pixmap,
+    path: &Path,
+    fill_rule: FillRule,
+    clip: Rect,
+    transform: Transform,
+    paint: Paint,
+) {
+    if clip.w <= 0 || clip.h <= 0 {
+        return;
+    }
+    let mut mask = Mask::new(pixmap.width(), pixmap.height());
+    if let (Some(mask), Some(clip)) = (
+        mask.as_mut(),
+        tiny_skia::Rect::from_xywh(clip.x as f32, clip.y as f32, clip.w as f32, clip.h as f32),
+    ) {
+        mask.fill_path(
+            &PathBuilder::from_rect(clip),
+            FillRule::Winding,
+            false,
+            Transform::identity(),
+        );
+    }
+    pixmap.fill_path(path, &paint, fill_rule, transform, mask.as_ref());
+}
+
+// Add a closed rect outline whose corners are quarter circles of `radius`, approximated with cubic curves.
+fn push_rounded_rect(path: &mut PathBuilder, rect: Rect, radius: f32) {
+    // distance of the control points from the corner ends, for a quarter circle of radius 1
+    const KAPPA: f32 = 0.552_284_8;
+    let (r, k) = (radius, radius * (1.0 - KAPPA));
+    let (x, y) = (rect.x as f32, rect.y as f32);
+    let (right, bottom) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+    path.move_to(x + r, y);
+    path.line_to(right - r, y);
+    path.cubic_to(right - k, y, right, y + k, right, y + r);
+    path.line_to(right, bottom - r);
+    path.cubic_to(right, bottom - k, right - k, bottom, right - r, bottom);
+    path.line_to(x + r, bottom);
+    path.cubic_to(x + k, bottom, x, bottom - k, x, bottom - r);
+    path.line_to(x, y + r);
+    path.cubic_to(x, y + k, x + k, y, x + r, y);
+    path.close();
+}
+
+// Decode to a premultiplied pixmap. Texture data is sRGB-encoded, as the renderer samples it.
+fn decode(texture_info: &TextureInfo, tint: glam::Vec4) -> Option<Pixmap> {
+    let mut image = texture_info.to_rgba8().ok()?;
+    draw::tint_rgba8(&mut image, tint);
+    let size = IntSize::from_wh(image.width(), image.height())?;
+    let mut pixels = image.into_raw();
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+        }
+    }
+    Pixmap::from_vec(pixels, size)
+}
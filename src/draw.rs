@@ -0,0 +1,539 @@
+//! Draw commands: the laid out scene as a flat, back-to-front list of primitives with resolved pixel geometry.
+//! The GPU renderer and the exporters consume this list instead of walking the scene tree themselves.
+
+use glam::{Affine2, Vec2, Vec4};
+
+use crate::{
+    NodeVisitor,
+    scene::{BlendMode, Scene},
+    texture::TextureId,
+    types::{Dimension, Rect},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum DrawCommand {
+    /// Solid rectangle. Color is linear RGBA.
+    /// Corners are rounded with `radius` pixels, at most half the shorter side.
+    FilledRect {
+        rect: Rect,
+        depth: i32,
+        color: Vec4,
+        radius: i32,
+        border: Option<Border>,
+    },
+
+    /// Rectangle filled with a linear gradient from `start` to `end` color, see [gradient_vector].
+    /// Colors are linear RGBA and are interpolated in linear space.
+    GradientRect {
+        rect: Rect,
+        depth: i32,
+        start: Vec4,
+        end: Vec4,
+        angle: f32,
+    },
+
+    /// Solid ellipse inscribed in the rect. Color is linear RGBA.
+    FilledEllipse { rect: Rect, depth: i32, color: Vec4 },
+
+    /// Rectangle showing the texture region from `uv_min` to `uv_max` in normalized texture coordinates,
+    /// with each texel multiplied by the linear RGBA `tint`.
+    /// The top-left corner of the rect samples `uv_min`, so `uv_min` larger than `uv_max` mirrors the region.
+    /// `translucent` quads have partially transparent texels, like anti-aliased glyphs, and are alpha blended.
+    /// The GPU renderer also blends quads whose tint or texture has translucent texels, found when it is uploaded.
+    TexturedQuad {
+        rect: Rect,
+        depth: i32,
+        texture_id: TextureId,
+        tint: Vec4,
+        uv_min: Vec2,
+        uv_max: Vec2,
+        translucent: bool,
+    },
+
+    /// Restrict the following commands to the rect, or lift the restriction with `None`.
+    Clip(Option<Rect>),
+
+    /// Map the geometry of the following commands from layout to screen coordinates, or stop with `None`.
+    /// Used for rotated nodes. Clip rects are in screen coordinates and not transformed.
+    Transform(Option<Affine2>),
+
+    /// Combine the following commands with what is behind them in the blend mode. [BlendMode::Replace] until set.
+    Blend(BlendMode),
+
+    /// Line through `points` in layout coordinates, `width` pixels wide, with bevel joins and flat ends.
+    /// There are at least two points and no two consecutive ones are equal. `rect` bounds the line and its width.
+    /// Color is linear RGBA.
+    Polyline {
+        rect: Rect,
+        depth: i32,
+        points: Vec<Vec2>,
+        width: f32,
+        color: Vec4,
+    },
+
+    /// Shadow of `rect` fading out from `color` across `blur` pixels on each side of its edge. Color is linear RGBA.
+    /// It covers `rect` grown by `blur`.
+    Shadow {
+        rect: Rect,
+        depth: i32,
+        color: Vec4,
+        blur: i32,
+    },
+
+    /// Checkerboard of `cell` pixel squares over the rect, starting with a `color` square at its top-left corner and
+    /// leaving the others empty. Color is linear RGBA. Used to show the space reserved by empty nodes when debugging.
+    Checkerboard {
+        rect: Rect,
+        depth: i32,
+        color: Vec4,
+        cell: i32,
+    },
+
+    /// Draw list `index` of [DrawList::groups] drawn on its own, then composited over `rect` at `alpha`.
+    /// The group's commands are in screen coordinates and start from the default state: no clip, no transform and
+    /// [BlendMode::Replace]. The composite itself is unclipped, untransformed and alpha blended.
+    Group {
+        rect: Rect,
+        depth: i32,
+        index: usize,
+        alpha: f32,
+    },
+}
+
+/// Outline drawn inside the edge of a [DrawCommand::FilledRect], over the fill color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Border {
+    /// Width in pixel, at most half the shorter side of the rect.
+    pub width: i32,
+    /// Linear RGBA.
+    pub color: Vec4,
+}
+
+impl Border {
+    /// Rect and corner radius of the area inside the border.
+    pub fn inner(&self, rect: Rect, radius: i32) -> (Rect, i32) {
+        let width = self.width;
+        let inner = Rect::new(
+            rect.x + width,
+            rect.y + width,
+            (rect.w - 2 * width).max(0),
+            (rect.h - 2 * width).max(0),
+        );
+        (inner, (radius - width).max(0))
+    }
+}
+
+impl DrawCommand {
+    /// Depth rank of a primitive, 0 being the nearest. `None` for state commands.
+    pub fn depth(&self) -> Option<i32> {
+        match self {
+            DrawCommand::FilledRect { depth, .. }
+            | DrawCommand::GradientRect { depth, .. }
+            | DrawCommand::FilledEllipse { depth, .. }
+            | DrawCommand::TexturedQuad { depth, .. }
+            | DrawCommand::Polyline { depth, .. }
+            | DrawCommand::Shadow { depth, .. }
+            | DrawCommand::Checkerboard { depth, .. }
+            | DrawCommand::Group { depth, .. } => Some(*depth),
+            DrawCommand::Clip(_) | DrawCommand::Transform(_) | DrawCommand::Blend(_) => None,
+        }
+    }
+
+    /// Rect covered by the primitive, before any transform.
+    pub fn rect(&self) -> Option<Rect> {
+        match self {
+            DrawCommand::FilledRect { rect, .. }
+            | DrawCommand::GradientRect { rect, .. }
+            | DrawCommand::FilledEllipse { rect, .. }
+            | DrawCommand::TexturedQuad { rect, .. }
+            | DrawCommand::Polyline { rect, .. }
+            | DrawCommand::Checkerboard { rect, .. }
+            | DrawCommand::Group { rect, .. } => Some(*rect),
+            DrawCommand::Shadow { rect, blur, .. } => Some(grow(*rect, *blur)),
+            DrawCommand::Clip(_) | DrawCommand::Transform(_) | DrawCommand::Blend(_) => None,
+        }
+    }
+
+    pub fn depth_mut(&mut self) -> Option<&mut i32> {
+        match self {
+            DrawCommand::FilledRect { depth, .. }
+            | DrawCommand::GradientRect { depth, .. }
+            | DrawCommand::FilledEllipse { depth, .. }
+            | DrawCommand::TexturedQuad { depth, .. }
+            | DrawCommand::Polyline { depth, .. }
+            | DrawCommand::Shadow { depth, .. }
+            | DrawCommand::Checkerboard { depth, .. }
+            | DrawCommand::Group { depth, .. } => Some(depth),
+            DrawCommand::Clip(_) | DrawCommand::Transform(_) | DrawCommand::Blend(_) => None,
+        }
+    }
+
+    // Decode the RGB channels of the primitive's colors from sRGB to linear.
+    pub fn decode_srgb(&mut self) {
+        let decode = |color: &mut Vec4| {
+            color.x = srgb_decode(color.x);
+            color.y = srgb_decode(color.y);
+            color.z = srgb_decode(color.z);
+        };
+        match self {
+            DrawCommand::FilledRect { color, border, .. } => {
+                decode(color);
+                if let Some(border) = border {
+                    decode(&mut border.color);
+                }
+            }
+            DrawCommand::GradientRect { start, end, .. } => {
+                decode(start);
+                decode(end);
+            }
+            DrawCommand::FilledEllipse { color, .. }
+            | DrawCommand::Polyline { color, .. }
+            | DrawCommand::Shadow { color, .. }
+            | DrawCommand::Checkerboard { color, .. } => decode(color),
+            DrawCommand::TexturedQuad { tint, .. } => decode(tint),
+            DrawCommand::Group { .. }
+            | DrawCommand::Clip(_)
+            | DrawCommand::Transform(_)
+            | DrawCommand::Blend(_) => (),
+        }
+    }
+
+    // Multiply the alpha of the primitive's colors.
+    fn fade(mut self, alpha: f32) -> Self {
+        match &mut self {
+            DrawCommand::FilledRect { color, border, .. } => {
+                color.w *= alpha;
+                if let Some(border) = border {
+                    border.color.w *= alpha;
+                }
+            }
+            DrawCommand::GradientRect { start, end, .. } => {
+                start.w *= alpha;
+                end.w *= alpha;
+            }
+            DrawCommand::FilledEllipse { color, .. }
+            | DrawCommand::Polyline { color, .. }
+            | DrawCommand::Shadow { color, .. }
+            | DrawCommand::Checkerboard { color, .. } => color.w *= alpha,
+            DrawCommand::TexturedQuad { tint, .. } => tint.w *= alpha,
+            DrawCommand::Group { alpha: group, .. } => *group *= alpha,
+            DrawCommand::Clip(_) | DrawCommand::Transform(_) | DrawCommand::Blend(_) => (),
+        }
+        self
+    }
+}
+
+/// Primitive with the scissor rect of its nearest clipping ancestor, the transform of its rotated ancestors and its
+/// blend mode.
+pub(crate) type Primitive = (Option<Rect>, Option<Affine2>, BlendMode, DrawCommand);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct DrawList {
+    /// Commands in drawing order, farthest first.
+    pub commands: Vec<DrawCommand>,
+    /// Number of distinct depth ranks among the commands.
+    pub depth_levels: i32,
+    /// Draw lists of the opacity groups, by the index of their [DrawCommand::Group]. Only the list of the whole
+    /// scene has them, and a group nested in another comes after it.
+    pub groups: Vec<DrawList>,
+}
+
+impl DrawList {
+    /// Order primitives with resolved depth farthest first, and insert the state commands between them.
+    pub fn new(mut primitives: Vec<Primitive>, depth_levels: i32) -> Self {
+        // Larger depth is farther, so it comes first. The sort is stable to keep traversal order within a depth.
+        primitives.sort_by_key(|(_, _, _, command)| std::cmp::Reverse(command.depth()));
+
+        let mut commands = Vec::with_capacity(primitives.len());
+        let mut current_clip = None;
+        let mut current_transform = None;
+        let mut current_blend = BlendMode::Replace;
+        for (clip, transform, blend, command) in primitives {
+            if clip != current_clip {
+                commands.push(DrawCommand::Clip(clip));
+                current_clip = clip;
+            }
+            if transform != current_transform {
+                commands.push(DrawCommand::Transform(transform));
+                current_transform = transform;
+            }
+            if blend != current_blend {
+                commands.push(DrawCommand::Blend(blend));
+                current_blend = blend;
+            }
+            commands.push(command);
+        }
+        Self {
+            commands,
+            depth_levels,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Commands with the opacity groups drawn in place, for the exporters that cannot draw offscreen.
+    /// The group alpha multiplies the alpha of each primitive of the group instead, so overlapping nodes of a group
+    /// show through each other.
+    pub fn flatten(&self) -> Vec<DrawCommand> {
+        let mut commands = Vec::with_capacity(self.commands.len());
+        self.flatten_into(&self.commands, 1.0, &mut commands);
+        commands
+    }
+
+    fn flatten_into(&self, commands: &[DrawCommand], alpha: f32, flat: &mut Vec<DrawCommand>) {
+        let mut clip = None;
+        let mut transform = None;
+        let mut blend = BlendMode::Replace;
+        for command in commands {
+            match command {
+                DrawCommand::Clip(rect) => {
+                    clip = *rect;
+                    flat.push(command.clone());
+                }
+                DrawCommand::Transform(affine) => {
+                    transform = *affine;
+                    flat.push(command.clone());
+                }
+                DrawCommand::Blend(mode) => {
+                    blend = *mode;
+                    flat.push(command.clone());
+                }
+                DrawCommand::Group {
+                    index,
+                    alpha: group_alpha,
+                    ..
+                } => {
+                    // the group starts from the default state, and the state is restored after it
+                    if let Some(group) = self.groups.get(*index) {
+                        flat.extend([
+                            DrawCommand::Clip(None),
+                            DrawCommand::Transform(None),
+                            DrawCommand::Blend(BlendMode::Replace),
+                        ]);
+                        self.flatten_into(&group.commands, alpha * group_alpha, flat);
+                        flat.extend([
+                            DrawCommand::Clip(clip),
+                            DrawCommand::Transform(transform),
+                            DrawCommand::Blend(blend),
+                        ]);
+                    }
+                }
+                primitive => flat.push(primitive.clone().fade(alpha)),
+            }
+        }
+    }
+}
+
+/// Lay the scene out on the screen and list what to draw, farthest first.
+pub(crate) fn lower(scene: &Scene, screen_size: Dimension) -> DrawList {
+    let visitor = NodeVisitor::visit(screen_size, scene);
+    let mut draw_list = DrawList::new(visitor.primitives, visitor.z_index);
+    draw_list.groups = visitor.groups;
+    draw_list
+}
+
+/// The rect with `amount` pixels added on each side, or removed if negative.
+pub(crate) fn grow(rect: Rect, amount: i32) -> Rect {
+    Rect::new(
+        rect.x - amount,
+        rect.y - amount,
+        (rect.w + 2 * amount).max(0),
+        (rect.h + 2 * amount).max(0),
+    )
+}
+
+/// Nested translucent rects approximating a [DrawCommand::Shadow] where nothing can be blurred, like in the
+/// exporters: from the rect grown by `blur` to the rect shrunk by it, stacking up to `color` inside.
+/// Empty rects are left out.
+pub(crate) fn shadow_layers(rect: Rect, blur: i32, color: Vec4) -> Vec<(Rect, Vec4)> {
+    const MAX_LAYERS: i32 = 8;
+    let layers = (2 * blur).clamp(1, MAX_LAYERS);
+    // alpha of each layer, so that all of them stacked reach the shadow's alpha
+    let alpha = 1.0 - (1.0 - color.w.clamp(0.0, 1.0)).powf(1.0 / layers as f32);
+    (0..layers)
+        .map(|layer| {
+            let amount = blur - (2 * blur * (2 * layer + 1)) / (2 * layers);
+            (grow(rect, amount), color.with_w(alpha))
+        })
+        .filter(|(rect, _)| rect.w > 0 && rect.h > 0)
+        .collect()
+}
+
+/// Filled squares of a [DrawCommand::Checkerboard], cut to the rect, for where no shader draws the pattern.
+pub(crate) fn checkerboard_cells(rect: Rect, cell: i32) -> Vec<Rect> {
+    let cell = cell.max(1);
+    let (columns, rows) = (
+        (rect.w.max(0) + cell - 1) / cell,
+        (rect.h.max(0) + cell - 1) / cell,
+    );
+    (0..rows)
+        .flat_map(|row| {
+            (row % 2..columns).step_by(2).map(move |column| {
+                Rect::new(rect.x + column * cell, rect.y + row * cell, cell, cell).intersect(rect)
+            })
+        })
+        .collect()
+}
+
+/// Direction of a linear gradient at `angle` radians in the unit square of its rect, y pointing down.
+/// Angle 0 runs left to right and PI/2 top to bottom. The gradient is centered on the rect and spans
+/// from `0.5 - v/2` to `0.5 + v/2`, so the rect's corners along the direction get exactly the end colors.
+pub(crate) fn gradient_vector(angle: f32) -> Vec2 {
+    let direction = Vec2::from_angle(angle);
+    direction * (direction.x.abs() + direction.y.abs())
+}
+
+/// Split `rect` into the nine regions of a nine-patch, with the texture region each one shows.
+/// `insets` are the top, right, bottom and left border widths in pixel, on the screen and in the texture alike,
+/// so the corners keep the texture's resolution. Borders wider than the rect are shrunk proportionally to fit,
+/// squashing the corners. Empty regions are left out.
+pub(crate) fn nine_patch(
+    rect: Rect,
+    texture_size: (u32, u32),
+    insets: [i32; 4],
+) -> Vec<(Rect, Vec2, Vec2)> {
+    let [top, right, bottom, left] = insets.map(|inset| inset.max(0));
+    // (screen offsets, texture coordinates) of the region edges along one axis
+    let edges = |start: i32, length: i32, before: i32, after: i32, texture_length: u32| {
+        let length = length.max(0);
+        let texture_length = texture_length.max(1) as f32;
+        let uv_before = (before as f32 / texture_length).min(1.0);
+        let uv_after = (after as f32 / texture_length).min(1.0 - uv_before);
+        let (before, after) = if before + after > length {
+            let before = (before as f32 * length as f32 / (before + after) as f32) as i32;
+            (before, length - before)
+        } else {
+            (before, after)
+        };
+        (
+            [
+                start,
+                start + before,
+                start + length - after,
+                start + length,
+            ],
+            [0.0, uv_before, 1.0 - uv_after, 1.0],
+        )
+    };
+    let (xs, us) = edges(rect.x, rect.w, left, right, texture_size.0);
+    let (ys, vs) = edges(rect.y, rect.h, top, bottom, texture_size.1);
+
+    let mut regions = Vec::with_capacity(9);
+    for row in 0..3 {
+        for column in 0..3 {
+            let region = Rect::new(
+                xs[column],
+                ys[row],
+                xs[column + 1] - xs[column],
+                ys[row + 1] - ys[row],
+            );
+            if region.w > 0 && region.h > 0 {
+                regions.push((
+                    region,
+                    Vec2::new(us[column], vs[row]),
+                    Vec2::new(us[column + 1], vs[row + 1]),
+                ));
+            }
+        }
+    }
+    regions
+}
+
+/// Encode a linear color channel to sRGB, as the sRGB surface does on write.
+pub(crate) fn srgb_encode(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decode an sRGB-encoded color channel to linear, as sampling an sRGB texture does.
+pub(crate) fn srgb_decode(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Multiply sRGB-encoded pixels by a linear tint, as the renderer does after sampling.
+pub(crate) fn tint_rgba8(image: &mut image::RgbaImage, tint: Vec4) {
+    if tint == Vec4::ONE {
+        return;
+    }
+    for pixel in image.pixels_mut() {
+        for (channel, factor) in pixel.0[..3].iter_mut().zip(tint.to_array()) {
+            let linear = srgb_decode(*channel as f32 / 255.0) * factor;
+            *channel = (srgb_encode(linear) * 255.0).round() as u8;
+        }
+        pixel.0[3] = (pixel.0[3] as f32 * tint.w.clamp(0.0, 1.0)).round() as u8;
+    }
+}
+
+/// Cut the region from `uv_min` to `uv_max` in normalized coordinates out of the image,
+/// rounded to whole pixels and at least one pixel large. The region is mirrored on the axes where `uv_min` is larger.
+pub(crate) fn crop_uv(image: image::RgbaImage, uv_min: Vec2, uv_max: Vec2) -> image::RgbaImage {
+    if uv_min == Vec2::ZERO && uv_max == Vec2::ONE {
+        return image;
+    }
+    let size = Vec2::new(image.width() as f32, image.height() as f32);
+    let min = (uv_min.min(uv_max).clamp(Vec2::ZERO, Vec2::ONE) * size).round();
+    let max = (uv_min.max(uv_max).clamp(Vec2::ZERO, Vec2::ONE) * size).round();
+    let (x, y) = (min.x as u32, min.y as u32);
+    let width = ((max.x - min.x) as u32).max(1);
+    let height = ((max.y - min.y) as u32).max(1);
+    let mut region = image::imageops::crop_imm(&image, x, y, width, height).to_image();
+    if uv_min.x > uv_max.x {
+        image::imageops::flip_horizontal_in_place(&mut region);
+    }
+    if uv_min.y > uv_max.y {
+        image::imageops::flip_vertical_in_place(&mut region);
+    }
+    region
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Guiug, Position, Size};
+
+    #[test]
+    fn lower_orders_commands_farthest_first() {
+        let mut guiug = Guiug::default();
+        let back = guiug.rect_node(Vec4::new(1.0, 0.0, 0.0, 1.0));
+        let front = guiug.rect_node(Vec4::new(0.0, 0.0, 1.0, 0.5));
+        let clip = guiug.clip_layer_node(vec![(Position::fill_with_margin(Size::Pixel(0)), front)]);
+        let root = guiug.layer_node(vec![
+            (Position::fill_with_margin(Size::Pixel(0)), back),
+            (Position::fill_with_margin(Size::Pixel(10)), clip),
+        ]);
+        guiug.set_root(root).unwrap();
+
+        let draw_list = lower(guiug.scene_mut(), Dimension::new(100, 80));
+        let inner = Rect::new(10, 10, 80, 60);
+        assert_eq!(
+            draw_list.commands,
+            [
+                DrawCommand::FilledRect {
+                    rect: Rect::new(0, 0, 100, 80),
+                    depth: 1,
+                    color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+                    radius: 0,
+                    border: None,
+                },
+                DrawCommand::Clip(Some(inner)),
+                DrawCommand::FilledRect {
+                    rect: inner,
+                    depth: 0,
+                    color: Vec4::new(0.0, 0.0, 1.0, 0.5),
+                    radius: 0,
+                    border: None,
+                },
+            ]
+        );
+        assert_eq!(draw_list.depth_levels, 2);
+        assert!(draw_list.groups.is_empty());
+    }
+}
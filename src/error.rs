@@ -0,0 +1,100 @@
+/// Failure to set up rendering.
+#[derive(Debug)]
+pub enum GuiugError {
+    /// The windowing event loop could not be created, e.g. without a display server.
+    EventLoop(winit::error::EventLoopError),
+    /// The window could not be created.
+    Window(winit::error::OsError),
+    /// The window could not be used as a render target.
+    CreateSurface(wgpu::CreateSurfaceError),
+    /// No graphics adapter is available, not even a software fallback.
+    AdapterRequest(wgpu::RequestAdapterError),
+    /// The adapter refused to create a device.
+    DeviceRequest(wgpu::RequestDeviceError),
+    /// The graphics adapter shared by the windows cannot draw to an additional window.
+    UnsupportedSurface,
+    /// Rendered pixels could not be read back from the GPU.
+    Readback(wgpu::BufferAsyncError),
+    /// A screenshot could not be written to its file.
+    SaveImage(image::ImageError),
+}
+
+impl std::fmt::Display for GuiugError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuiugError::EventLoop(err) => write!(f, "failed to run the event loop: {err}"),
+            GuiugError::Window(err) => write!(f, "failed to create the window: {err}"),
+            GuiugError::CreateSurface(err) => {
+                write!(f, "failed to create the window surface: {err}")
+            }
+            GuiugError::AdapterRequest(err) => {
+                write!(f, "failed to find a graphics adapter: {err}")
+            }
+            GuiugError::DeviceRequest(err) => {
+                write!(f, "failed to create a graphics device: {err}")
+            }
+            GuiugError::UnsupportedSurface => {
+                write!(f, "the graphics adapter cannot draw to the window")
+            }
+            GuiugError::Readback(err) => write!(f, "failed to read rendered pixels: {err}"),
+            GuiugError::SaveImage(err) => write!(f, "failed to save the image: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for GuiugError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GuiugError::EventLoop(err) => Some(err),
+            GuiugError::Window(err) => Some(err),
+            GuiugError::CreateSurface(err) => Some(err),
+            GuiugError::AdapterRequest(err) => Some(err),
+            GuiugError::DeviceRequest(err) => Some(err),
+            GuiugError::UnsupportedSurface => None,
+            GuiugError::Readback(err) => Some(err),
+            GuiugError::SaveImage(err) => Some(err),
+        }
+    }
+}
+
+impl From<winit::error::EventLoopError> for GuiugError {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        GuiugError::EventLoop(err)
+    }
+}
+
+impl From<winit::error::OsError> for GuiugError {
+    fn from(err: winit::error::OsError) -> Self {
+        GuiugError::Window(err)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for GuiugError {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        GuiugError::CreateSurface(err)
+    }
+}
+
+impl From<wgpu::RequestAdapterError> for GuiugError {
+    fn from(err: wgpu::RequestAdapterError) -> Self {
+        GuiugError::AdapterRequest(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for GuiugError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        GuiugError::DeviceRequest(err)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for GuiugError {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        GuiugError::Readback(err)
+    }
+}
+
+impl From<image::ImageError> for GuiugError {
+    fn from(err: image::ImageError) -> Self {
+        GuiugError::SaveImage(err)
+    }
+}
@@ -0,0 +1,100 @@
+use crate::scene::Scene;
+
+pub(crate) type EventHandler<'a> = Box<dyn FnMut(&mut Scene, UiEvent) + 'a>;
+
+pub(crate) type UpdateHandler<'a> = Box<dyn FnMut(&mut Scene, f32) + 'a>;
+
+pub(crate) type RenderHandler<'a> =
+    Box<dyn FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::RenderPass<'_>) + 'a>;
+
+/// Input event delivered to the event handler given to [crate::run_with].
+#[derive(Clone, Debug, PartialEq)]
+pub enum UiEvent {
+    /// Cursor moved inside the window.
+    /// * `position` - (x, y) pixel position from the top-left corner of the window.
+    CursorMoved { position: (i32, i32) },
+
+    /// Mouse button pressed or released.
+    /// * `position` - cursor position when the button state changed.
+    MouseInput {
+        button: MouseButton,
+        pressed: bool,
+        position: (i32, i32),
+    },
+
+    /// Mouse wheel or touchpad scrolled. The Scroll node under the cursor is scrolled before the handler is called.
+    /// * `delta` - (x, y) pixels scrolled. Positive y scrolls up, revealing content above, as when the wheel is
+    ///   turned away from the user. Positive x scrolls left. Wheels that scroll by lines move [LINE_HEIGHT] pixels per line.
+    /// * `position` - cursor position when scrolling.
+    MouseWheel {
+        delta: (f32, f32),
+        position: (i32, i32),
+    },
+
+    /// Key pressed or released while the window has focus.
+    /// * `key` - logical key with the keyboard layout applied, e.g. `Key::Character("a")`, or `"A"` with shift held.
+    /// * `modifiers` - modifier keys held when the key state changed.
+    KeyboardInput {
+        key: winit::keyboard::Key,
+        pressed: bool,
+        modifiers: Modifiers,
+    },
+}
+
+/// Modifier keys held during a [UiEvent::KeyboardInput].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// Windows key on PC, command key on Mac.
+    pub logo: bool,
+}
+
+impl From<winit::keyboard::ModifiersState> for Modifiers {
+    fn from(state: winit::keyboard::ModifiersState) -> Self {
+        Self {
+            shift: state.shift_key(),
+            ctrl: state.control_key(),
+            alt: state.alt_key(),
+            logo: state.super_key(),
+        }
+    }
+}
+
+/// Pixels scrolled per line by wheels that report lines instead of pixels.
+pub const LINE_HEIGHT: f32 = 40.0;
+
+// Convert a winit scroll delta to pixels, keeping winit's sign convention.
+pub(crate) fn wheel_delta(delta: winit::event::MouseScrollDelta) -> (f32, f32) {
+    match delta {
+        winit::event::MouseScrollDelta::LineDelta(x, y) => (x * LINE_HEIGHT, y * LINE_HEIGHT),
+        winit::event::MouseScrollDelta::PixelDelta(position) => {
+            (position.x as f32, position.y as f32)
+        }
+    }
+}
+
+/// Mouse button of [UiEvent::MouseInput].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    Other(u16),
+}
+
+impl From<winit::event::MouseButton> for MouseButton {
+    fn from(button: winit::event::MouseButton) -> Self {
+        match button {
+            winit::event::MouseButton::Left => MouseButton::Left,
+            winit::event::MouseButton::Right => MouseButton::Right,
+            winit::event::MouseButton::Middle => MouseButton::Middle,
+            winit::event::MouseButton::Back => MouseButton::Back,
+            winit::event::MouseButton::Forward => MouseButton::Forward,
+            winit::event::MouseButton::Other(other) => MouseButton::Other(other),
+        }
+    }
+}
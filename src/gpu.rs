@@ -0,0 +1,961 @@
+//! GPU resources and drawing shared by the window and headless rendering.
+
+use glam::UVec3;
+use wgpu::{BindGroupDescriptor, BindGroupLayoutDescriptor, util::DeviceExt};
+
+use crate::{
+    draw,
+    error::GuiugError,
+    event, renderer,
+    scene::{FrameStats, Scene},
+    texture::{self, TextureError, TextureId, TextureInfoManager},
+    types::Dimension,
+};
+
+// Size of the staging belt's buffers, which fits every instance buffer. Larger uploads get a buffer of their own.
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 1 << 18;
+
+/// Which graphics API and which adapter to render with, set with [crate::Guiug::set_gpu_config].
+/// The `WGPU_BACKEND` (e.g. `vulkan`, `gl`) and `WGPU_POWER_PREF` (`low`, `high`) environment variables
+/// override these settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuConfig {
+    pub backend: Backend,
+    pub power_preference: PowerPreference,
+    /// MSAA samples per pixel: 1, 2, 4 or 8. A count the adapter does not support falls back to the highest
+    /// supported lower one, reported by [crate::Scene::sample_count]. Default is 1.
+    pub sample_count: u32,
+    /// Depth buffer the nodes are drawn with. Default is [DepthConfig::Depth32Float].
+    pub depth: DepthConfig,
+    /// Draw the edges of the triangles instead of filling them, to debug the geometry of the nodes.
+    /// Needs the `POLYGON_MODE_LINE` device feature; without it, a warning is printed and triangles are filled.
+    /// Every window draws with the device of the application passed to [crate::run], so the feature is only
+    /// requested if its gpu config enables wireframe. Default is false.
+    pub wireframe: bool,
+    /// Skip triangles facing away from the screen, wound clockwise once transformed. Disable it to debug the winding
+    /// of geometry, or when a transform mirrors nodes, which would make them vanish. Default is true.
+    pub cull_back_faces: bool,
+}
+
+impl Default for GpuConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            power_preference: PowerPreference::default(),
+            sample_count: 1,
+            depth: DepthConfig::default(),
+            wireframe: false,
+            cull_back_faces: true,
+        }
+    }
+}
+
+/// Depth buffer format, or no depth buffer at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DepthConfig {
+    /// No depth buffer, saving its memory and bandwidth. Every node is drawn back-to-front like translucent ones,
+    /// which costs more draw calls, and textured nodes (textures, nine-patches and text) are drawn after all the
+    /// others, so they show above overlapping rects and shapes whatever their order in the scene.
+    None,
+    #[default]
+    Depth32Float,
+    /// Depth format of at least 24 bits chosen by the backend, which may take less memory than `Depth32Float`.
+    Depth24Plus,
+}
+
+impl DepthConfig {
+    pub(crate) fn format(self) -> Option<wgpu::TextureFormat> {
+        match self {
+            DepthConfig::None => None,
+            DepthConfig::Depth32Float => Some(wgpu::TextureFormat::Depth32Float),
+            DepthConfig::Depth24Plus => Some(wgpu::TextureFormat::Depth24Plus),
+        }
+    }
+}
+
+/// Graphics API used for rendering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Best supported API of the platform: Vulkan, Metal, DX12 or WebGPU.
+    #[default]
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// Adapter preferred when the machine has several GPUs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PowerPreference {
+    /// Usually the integrated GPU.
+    LowPower,
+    /// Usually the discrete GPU.
+    #[default]
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+impl GpuConfig {
+    pub(crate) fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::from(self.backend).with_env(),
+            ..Default::default()
+        })
+    }
+
+    /// Highest supported sample count up to [Self::sample_count] for `color_format` and the depth buffer.
+    pub(crate) fn select_sample_count(
+        &self,
+        adapter: &wgpu::Adapter,
+        color_format: wgpu::TextureFormat,
+    ) -> u32 {
+        let color = adapter.get_texture_format_features(color_format).flags;
+        let depth = self
+            .depth
+            .format()
+            .map(|format| adapter.get_texture_format_features(format).flags);
+        [8, 4, 2]
+            .into_iter()
+            .find(|count| {
+                *count <= self.sample_count
+                    && color.sample_count_supported(*count)
+                    && depth.is_none_or(|depth| depth.sample_count_supported(*count))
+            })
+            .unwrap_or(1)
+    }
+
+    /// Pipeline config drawing to `color_format` with the device of `adapter`.
+    pub(crate) fn pipeline_config(
+        &self,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+    ) -> renderer::PipelineConfig {
+        let polygon_mode = if !self.wireframe {
+            wgpu::PolygonMode::Fill
+        } else if device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            wgpu::PolygonMode::Line
+        } else {
+            eprintln!("guiug: the device does not support wireframe, filling triangles instead");
+            wgpu::PolygonMode::Fill
+        };
+        renderer::PipelineConfig {
+            format: color_format,
+            sample_count: self.select_sample_count(adapter, color_format),
+            depth_format: self.depth.format(),
+            polygon_mode,
+            cull_mode: self.cull_back_faces.then_some(wgpu::Face::Back),
+        }
+    }
+
+    /// Device with the features the config needs, among those the adapter supports.
+    pub(crate) fn device_descriptor(
+        &self,
+        adapter: &wgpu::Adapter,
+    ) -> wgpu::DeviceDescriptor<'static> {
+        let mut required_features = wgpu::Features::empty();
+        if self.wireframe {
+            required_features |= adapter.features() & wgpu::Features::POLYGON_MODE_LINE;
+        }
+        wgpu::DeviceDescriptor {
+            required_features,
+            ..Default::default()
+        }
+    }
+
+    /// Request an adapter, retrying with a software fallback adapter when no hardware adapter is available.
+    /// Returns the adapter and whether it is the fallback one. Fails only if both requests fail.
+    pub(crate) async fn request_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+    ) -> Result<(wgpu::Adapter, bool), wgpu::RequestAdapterError> {
+        let options = |force_fallback_adapter| wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::from_env()
+                .unwrap_or(self.power_preference.into()),
+            force_fallback_adapter,
+            compatible_surface: surface,
+        };
+        match instance.request_adapter(&options(false)).await {
+            Ok(adapter) => Ok((adapter, false)),
+            Err(_) => Ok((instance.request_adapter(&options(true)).await?, true)),
+        }
+    }
+}
+
+/// Instance, adapter and device shared by every window of [crate::run].
+pub(crate) struct SharedDevice {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    // whether the adapter is the software fallback one
+    pub fallback_adapter: bool,
+}
+
+impl SharedDevice {
+    /// Set up a device that can draw to `window`. Returns it with the window's surface.
+    pub async fn new(
+        config: GpuConfig,
+        window: std::sync::Arc<winit::window::Window>,
+    ) -> Result<(Self, wgpu::Surface<'static>), GuiugError> {
+        let instance = config.instance();
+        let surface = instance.create_surface(window)?;
+        let (adapter, fallback_adapter) = config.request_adapter(&instance, Some(&surface)).await?;
+        let (device, queue) = adapter
+            .request_device(&config.device_descriptor(&adapter))
+            .await?;
+        Ok((
+            Self {
+                instance,
+                adapter,
+                device,
+                queue,
+                fallback_adapter,
+            },
+            surface,
+        ))
+    }
+}
+
+pub(crate) struct Gpu {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+
+    flat_renderer: renderer::FlatRenderer,
+    texture_renderer: renderer::TextureRenderer,
+    // renderer of the uploaded translucent instances back-to-front, see [renderer::Batches::translucent_order]
+    translucent_order: Vec<renderer::TranslucentRun>,
+    screen_uniform_buffer: wgpu::Buffer,
+    screen_uniform_bind_group: wgpu::BindGroup,
+
+    texture_manager: texture::TextureManager,
+    pub config: renderer::PipelineConfig,
+    // multisampled color target resolved into the drawn view, when sample_count is more than 1
+    msaa_target: Option<(Dimension, wgpu::TextureView)>,
+    // screen size and instance counts of the uploaded instances, `None` before the first upload
+    uploaded: Option<(Dimension, FrameStats)>,
+    // renderers of the opacity groups by group index, each with its own instance buffers as every group is drawn
+    // in the same submission, and the order of their translucent instances
+    group_renderers: Vec<(
+        renderer::FlatRenderer,
+        renderer::TextureRenderer,
+        Vec<renderer::TranslucentRun>,
+    )>,
+    // number of opacity groups in the uploaded instances
+    uploaded_groups: usize,
+    // depth buffer and multisampled color target shared by the passes of the opacity groups, one after another
+    group_buffers: Option<(
+        Dimension,
+        Option<wgpu::TextureView>,
+        Option<wgpu::TextureView>,
+    )>,
+    // stages the instance uploads of a frame, copied into the instance buffers by the frame's encoder
+    staging_belt: wgpu::util::StagingBelt,
+    // instances of the last uploaded draw list, kept to reuse the allocations on the next upload
+    batches: renderer::Batches,
+
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    screen_bind_group_layout: wgpu::BindGroupLayout,
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    shader_watcher: crate::hot_reload::ShaderWatcher,
+}
+
+impl Gpu {
+    /// Set up renderers drawing with the pipeline `config`. Depth buffers must be created with its depth format and
+    /// sample count.
+    pub fn new(device: wgpu::Device, queue: wgpu::Queue, config: renderer::PipelineConfig) -> Self {
+        let texture_manager = texture::TextureManager::new(&device);
+
+        // screen uniform
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let screen_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("screen uniform buffer"),
+            contents: bytemuck::cast_slice(&[UVec3::ZERO]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let screen_uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("screen uniform bind group"),
+            layout: &screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &screen_uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            }],
+        });
+
+        // renderer
+        let flat_renderer = renderer::FlatRenderer::new(
+            &device,
+            &device.create_shader_module(wgpu::include_wgsl!("shader/flat.wgsl")),
+            config,
+            &screen_bind_group_layout,
+        );
+
+        let texture_renderer = renderer::TextureRenderer::new(
+            &device,
+            &device.create_shader_module(wgpu::include_wgsl!("shader/texture.wgsl")),
+            config,
+            &screen_bind_group_layout,
+            &texture_manager.bind_group_layout,
+            &texture_manager.array_bind_group_layout,
+        );
+
+        let staging_belt = wgpu::util::StagingBelt::new(device.clone(), STAGING_CHUNK_SIZE);
+
+        Self {
+            device,
+            queue,
+            flat_renderer,
+            texture_renderer,
+            translucent_order: Vec::new(),
+            screen_uniform_buffer,
+            screen_uniform_bind_group,
+            texture_manager,
+            config,
+            msaa_target: None,
+            uploaded: None,
+            group_renderers: Vec::new(),
+            uploaded_groups: 0,
+            group_buffers: None,
+            staging_belt,
+            batches: renderer::Batches::default(),
+            #[cfg(all(feature = "hot-reload", debug_assertions))]
+            screen_bind_group_layout,
+            #[cfg(all(feature = "hot-reload", debug_assertions))]
+            shader_watcher: crate::hot_reload::ShaderWatcher::new(),
+        }
+    }
+
+    /// Rebuild the renderers whose shader file was modified since the last call.
+    /// Returns true if any was rebuilt, so that the frame has to be drawn again.
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    pub fn reload_shaders(&mut self) -> bool {
+        use crate::hot_reload::try_build;
+
+        let [flat, texture] = self.shader_watcher.changed();
+        let flat = flat.and_then(|source| {
+            try_build(&self.device, "flat.wgsl", source, |shader| {
+                renderer::FlatRenderer::new(
+                    &self.device,
+                    shader,
+                    self.config,
+                    &self.screen_bind_group_layout,
+                )
+            })
+        });
+        let texture = texture.and_then(|source| {
+            try_build(&self.device, "texture.wgsl", source, |shader| {
+                renderer::TextureRenderer::new(
+                    &self.device,
+                    shader,
+                    self.config,
+                    &self.screen_bind_group_layout,
+                    &self.texture_manager.bind_group_layout,
+                    &self.texture_manager.array_bind_group_layout,
+                )
+            })
+        });
+
+        let reloaded = flat.is_some() || texture.is_some();
+        if let Some(flat) = flat {
+            self.flat_renderer = flat;
+        }
+        if let Some(texture) = texture {
+            self.texture_renderer = texture;
+        }
+        // the new renderers have empty instance buffers, and those of the groups share the previous pipelines
+        if reloaded {
+            self.uploaded = None;
+            self.group_renderers.clear();
+        }
+        reloaded
+    }
+
+    /// Upload every texture of the builder and the scene. Returns the ones that failed.
+    pub fn load_textures(
+        &mut self,
+        scene: &Scene,
+        texture_infos: Option<&TextureInfoManager>,
+    ) -> Vec<(TextureId, TextureError)> {
+        let mut errors = Vec::new();
+        if let Some(texture_infos) = texture_infos {
+            errors = self
+                .texture_manager
+                .load(&self.device, &self.queue, texture_infos);
+        }
+        errors.extend(
+            self.texture_manager
+                .load(&self.device, &self.queue, &scene.texture_infos),
+        );
+        self.texture_manager
+            .prepare_array(&self.device, &self.queue);
+        errors
+    }
+
+    /// Upload textures registered or updated through the scene since the last frame.
+    pub fn upload_pending_textures(&mut self, scene: &mut Scene) {
+        for texture_id in std::mem::take(&mut scene.pending_textures) {
+            let Some(texture_info) = scene.texture_infos.get(texture_id) else {
+                continue;
+            };
+            let result = match &texture_info.data {
+                texture::TextureData::Rgba8 {
+                    width,
+                    height,
+                    pixels,
+                } if self.texture_manager.get_texture(texture_id).is_some() => self
+                    .texture_manager
+                    .update_texture(&self.queue, texture_id, *width, *height, pixels),
+                // a new texture rebuilds the array below
+                _ => self
+                    .texture_manager
+                    .load_one(&self.device, &self.queue, texture_id, texture_info)
+                    .map(|()| false),
+            };
+            match result {
+                Ok(translucency_changed) => {
+                    // the uploaded instances of the texture are batched as opaque or translucent
+                    if translucency_changed {
+                        scene.dirty = true;
+                    }
+                    // a new size changes the layout of Size::AspectOf
+                    if let Some(size) = self.texture_manager.texture_size(texture_id)
+                        && scene.texture_sizes.insert(texture_id, size) != Some(size)
+                    {
+                        scene.dirty = true;
+                    }
+                }
+                Err(err) => scene.texture_errors.push((texture_id, err)),
+            }
+        }
+        // the uploaded instances refer to layers of the previous array
+        if self
+            .texture_manager
+            .prepare_array(&self.device, &self.queue)
+        {
+            scene.dirty = true;
+        }
+    }
+
+    // Lay the scene out, stage its instances for copy by `encoder` and write the screen uniform to the GPU.
+    // Returns the instance counts, with no draw calls yet.
+    fn upload(
+        &mut self,
+        scene: &Scene,
+        screen_size: Dimension,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> FrameStats {
+        let draw_list = draw::lower(scene, screen_size);
+        let batches = &mut self.batches;
+        let translucent_texture = |texture_id| {
+            self.texture_manager
+                .get_texture(texture_id)
+                .is_some_and(|texture| texture.translucent)
+        };
+        batches.fill(
+            &draw_list.commands,
+            self.config.depth_format.is_some(),
+            translucent_texture,
+        );
+
+        // the groups share the screen uniform, so depth is spread over the levels of the deepest list
+        let depth_levels = draw_list
+            .groups
+            .iter()
+            .map(|group| group.depth_levels)
+            .fold(draw_list.depth_levels, i32::max);
+        self.queue.write_buffer(
+            &self.screen_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[UVec3::new(
+                screen_size.width as u32,
+                screen_size.height as u32,
+                depth_levels as u32,
+            )]),
+        );
+
+        let mut frame_stats = FrameStats {
+            draw_calls: 0,
+            rect_instances: (batches.rects.len() + batches.transparent_rects.len()) as u32,
+            texture_instances: (batches.textures.len() + batches.transparent_textures.len()) as u32,
+        };
+        self.flat_renderer.upload(
+            &self.device,
+            &mut self.staging_belt,
+            encoder,
+            &mut batches.rects,
+            &batches.transparent_rects,
+        );
+        self.texture_renderer.upload(
+            &self.device,
+            &mut self.staging_belt,
+            encoder,
+            &self.texture_manager,
+            &mut batches.textures,
+            &batches.transparent_textures,
+        );
+        self.translucent_order
+            .clone_from(&batches.translucent_order);
+
+        while self.group_renderers.len() < draw_list.groups.len() {
+            self.group_renderers.push((
+                self.flat_renderer.share(&self.device),
+                self.texture_renderer.share(&self.device),
+                Vec::new(),
+            ));
+        }
+        for (group, (flat_renderer, texture_renderer, translucent_order)) in
+            draw_list.groups.iter().zip(&mut self.group_renderers)
+        {
+            batches.fill(
+                &group.commands,
+                self.config.depth_format.is_some(),
+                translucent_texture,
+            );
+            frame_stats.rect_instances +=
+                (batches.rects.len() + batches.transparent_rects.len()) as u32;
+            frame_stats.texture_instances +=
+                (batches.textures.len() + batches.transparent_textures.len()) as u32;
+            flat_renderer.upload(
+                &self.device,
+                &mut self.staging_belt,
+                encoder,
+                &mut batches.rects,
+                &batches.transparent_rects,
+            );
+            texture_renderer.upload(
+                &self.device,
+                &mut self.staging_belt,
+                encoder,
+                &self.texture_manager,
+                &mut batches.textures,
+                &batches.transparent_textures,
+            );
+            translucent_order.clone_from(&batches.translucent_order);
+        }
+        self.uploaded_groups = draw_list.groups.len();
+        frame_stats
+    }
+
+    // Draw every opacity group into its target, nested groups first so that the groups containing them can
+    // composite them. The targets and buffers follow the screen size, and are dropped when there are no groups.
+    fn draw_groups(&mut self, encoder: &mut wgpu::CommandEncoder, screen_size: Dimension) -> u32 {
+        let size = (
+            screen_size.width.max(1) as u32,
+            screen_size.height.max(1) as u32,
+        );
+        self.texture_manager.prepare_group_targets(
+            &self.device,
+            self.uploaded_groups,
+            size,
+            self.config.format,
+        );
+        if self.uploaded_groups == 0 {
+            self.group_buffers = None;
+            return 0;
+        }
+        if self
+            .group_buffers
+            .as_ref()
+            .is_none_or(|(buffers_size, ..)| *buffers_size != screen_size)
+        {
+            let depth_view = self.config.depth_format.map(|format| {
+                texture::create_depth_texture(
+                    &self.device,
+                    size.0,
+                    size.1,
+                    self.config.sample_count,
+                    format,
+                )
+            });
+            let msaa_view =
+                (self.config.sample_count > 1).then(|| self.create_msaa_target(screen_size));
+            self.group_buffers = Some((screen_size, depth_view, msaa_view));
+        }
+        let Some((_, depth_view, msaa_view)) = &self.group_buffers else {
+            return 0;
+        };
+
+        let mut draw_calls = 0;
+        for index in (0..self.uploaded_groups).rev() {
+            let (Some(target), Some((flat_renderer, texture_renderer, translucent_order))) = (
+                self.texture_manager.group_targets.get(index),
+                self.group_renderers.get(index),
+            ) else {
+                continue;
+            };
+            let target_view = target.view();
+            let (view, resolve_target, store) = match msaa_view {
+                Some(msaa_view) => (msaa_view, Some(&target_view), wgpu::StoreOp::Discard),
+                None => (&target_view, None, wgpu::StoreOp::Store),
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("opacity group"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    depth_slice: None,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store,
+                    },
+                })],
+                depth_stencil_attachment: depth_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Discard,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                ..Default::default()
+            });
+            render_pass.set_bind_group(0, &self.screen_uniform_bind_group, &[]);
+            draw_calls += draw_renderers(
+                &mut render_pass,
+                (flat_renderer, texture_renderer, translucent_order),
+                &self.texture_manager,
+                screen_size,
+            );
+        }
+        draw_calls
+    }
+
+    fn create_msaa_target(&self, screen_size: Dimension) -> wgpu::TextureView {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa target"),
+            size: wgpu::Extent3d {
+                width: screen_size.width.max(1) as u32,
+                height: screen_size.height.max(1) as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.config.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Draw the scene. Instances are rebuilt and uploaded only if the scene is dirty or the screen size changed.
+    pub fn draw(
+        &mut self,
+        scene: &Scene,
+        color_view: &wgpu::TextureView,
+        depth_view: Option<&wgpu::TextureView>,
+        screen_size: Dimension,
+        render_handler: Option<&mut event::RenderHandler>,
+    ) -> FrameStats {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        // Rebuild instances only when the scene or the screen changed, and reuse the uploaded ones otherwise.
+        let mut frame_stats = match self.uploaded {
+            Some((uploaded_size, frame_stats)) if !scene.dirty && uploaded_size == screen_size => {
+                frame_stats
+            }
+            _ => {
+                let frame_stats = self.upload(scene, screen_size, &mut encoder);
+                self.uploaded = Some((screen_size, frame_stats));
+                frame_stats
+            }
+        };
+
+        // the opacity groups are drawn first, as the main pass composites their targets
+        frame_stats.draw_calls += self.draw_groups(&mut encoder, screen_size);
+
+        // With MSAA, samples are drawn to the multisampled target and resolved into `color_view`.
+        if self.config.sample_count > 1
+            && self
+                .msaa_target
+                .as_ref()
+                .is_none_or(|(size, _)| *size != screen_size)
+        {
+            self.msaa_target = Some((screen_size, self.create_msaa_target(screen_size)));
+        }
+        let (view, resolve_target, store) = match &self.msaa_target {
+            Some((_, msaa_view)) if self.config.sample_count > 1 => {
+                (msaa_view, Some(color_view), wgpu::StoreOp::Discard)
+            }
+            _ => (color_view, None, wgpu::StoreOp::Store),
+        };
+        let background = scene.background();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    depth_slice: None,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: background.x as f64,
+                            g: background.y as f64,
+                            b: background.z as f64,
+                            a: background.w as f64,
+                        }),
+                        store,
+                    },
+                })],
+                depth_stencil_attachment: depth_view.map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                ..Default::default()
+            });
+
+            // bind screen uniform
+            render_pass.set_bind_group(0, &self.screen_uniform_bind_group, &[]);
+
+            frame_stats.draw_calls += draw_renderers(
+                &mut render_pass,
+                (
+                    &self.flat_renderer,
+                    &self.texture_renderer,
+                    &self.translucent_order,
+                ),
+                &self.texture_manager,
+                screen_size,
+            );
+
+            // User rendering, sharing the pass and its depth buffer
+            if let Some(render_handler) = render_handler {
+                render_handler(&self.device, &self.queue, &mut render_pass);
+            }
+        }
+
+        // the staged copies run before the passes of the same submission, and the chunks are reused once done
+        self.staging_belt.finish();
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.staging_belt.recall();
+        frame_stats
+    }
+
+    /// Draw the scene to an offscreen texture of the color format and read its pixels back as sRGB-encoded RGBA.
+    /// Blocks until the pixels are read back.
+    pub fn capture(
+        &mut self,
+        scene: &Scene,
+        screen_size: Dimension,
+        render_handler: Option<&mut event::RenderHandler>,
+    ) -> Result<image::RgbaImage, GuiugError> {
+        let width = screen_size.width.max(1) as u32;
+        let height = screen_size.height.max(1) as u32;
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let target = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let color_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = self.config.depth_format.map(|format| {
+            texture::create_depth_texture(
+                &self.device,
+                width,
+                height,
+                self.config.sample_count,
+                format,
+            )
+        });
+
+        self.draw(
+            scene,
+            &color_view,
+            depth_view.as_ref(),
+            Dimension::new(width as i32, height as i32),
+            render_handler,
+        );
+
+        // rows of a texture copy must be aligned
+        let unpadded_bytes_per_row = 4 * width;
+        let bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback"),
+            size: (bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+        receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let mapped = buffer.slice(..).get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in mapped.chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(mapped);
+        buffer.unmap();
+
+        // window surfaces are commonly BGRA
+        if matches!(
+            self.config.format,
+            wgpu::TextureFormat::Bgra8UnormSrgb | wgpu::TextureFormat::Bgra8Unorm
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(image::RgbaImage::from_raw(width, height, pixels).expect("readback has the image size"))
+    }
+}
+
+// Draw the uploaded instances of the renderers, with the order of their translucent instances. Returns the number of
+// draw calls.
+fn draw_renderers(
+    render_pass: &mut wgpu::RenderPass,
+    (flat_renderer, texture_renderer, translucent_order): (
+        &renderer::FlatRenderer,
+        &renderer::TextureRenderer,
+        &[renderer::TranslucentRun],
+    ),
+    texture_manager: &texture::TextureManager,
+    screen_size: Dimension,
+) -> u32 {
+    // Flat rendering
+    let mut draw_calls = flat_renderer.draw(render_pass, screen_size);
+
+    // Texture rendering
+    draw_calls += texture_renderer.draw(render_pass, texture_manager, screen_size);
+
+    // Translucent rendering, after all opaque nodes, back-to-front across both renderers
+    let (mut flat_start, mut texture_start) = (0, 0);
+    for &(kind, count) in translucent_order {
+        draw_calls += match kind {
+            renderer::TranslucentKind::Flat => {
+                flat_start += count;
+                flat_renderer.draw_transparent(
+                    render_pass,
+                    screen_size,
+                    flat_start - count..flat_start,
+                )
+            }
+            renderer::TranslucentKind::Texture => {
+                texture_start += count;
+                texture_renderer.draw_transparent(
+                    render_pass,
+                    texture_manager,
+                    screen_size,
+                    texture_start - count..texture_start,
+                )
+            }
+        };
+    }
+    draw_calls
+}
+
+/// Render the scene to an image with a GPU device that has no surface.
+pub(crate) async fn render_to_image(
+    scene: &Scene,
+    texture_infos: Option<&TextureInfoManager<'_>>,
+    config: GpuConfig,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage, GuiugError> {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    let instance = config.instance();
+    let (adapter, _) = config.request_adapter(&instance, None).await?;
+    let (device, queue) = adapter
+        .request_device(&config.device_descriptor(&adapter))
+        .await?;
+
+    let pipeline_config = config.pipeline_config(&adapter, &device, FORMAT);
+    let mut gpu = Gpu::new(device, queue, pipeline_config);
+    // textures that fail to load draw nothing, as in the window
+    gpu.load_textures(scene, texture_infos);
+
+    gpu.capture(
+        scene,
+        Dimension::new(width.max(1) as i32, height.max(1) as i32),
+        None,
+    )
+}
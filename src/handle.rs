@@ -0,0 +1,49 @@
+//! Scene updates sent from other threads to a running window.
+
+use std::sync::{Arc, Mutex, mpsc};
+
+use crate::scene::Scene;
+
+/// Scene mutation queued by a [SceneHandle].
+pub(crate) type Update = Box<dyn FnOnce(&mut Scene) + Send>;
+
+/// Handle applying updates to the scene of a running window from other threads, e.g. a download task updating a
+/// progress bar. Get it with [crate::Guiug::scene_handle] before calling [crate::run], and clone it for each thread.
+#[derive(Clone)]
+pub struct SceneHandle {
+    sender: mpsc::Sender<Update>,
+    // wakes the event loop once it runs
+    pub(crate) waker: Arc<Mutex<Option<winit::event_loop::EventLoopProxy<()>>>>,
+}
+
+impl SceneHandle {
+    pub(crate) fn new() -> (Self, mpsc::Receiver<Update>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = Self {
+            sender,
+            waker: Arc::new(Mutex::new(None)),
+        };
+        (handle, receiver)
+    }
+
+    /// Queue `update` to be called with the scene on the window's thread at the start of the next frame, and wake the
+    /// window to draw it. Updates run in the order they were sent, and those sent before the window opens run before
+    /// its first frame.
+    ///
+    /// Node ids returned by the scene inside `update` are allocated on the window's thread when it runs, so other
+    /// threads only learn them if `update` sends them back, e.g. through a channel. Ids of nodes created before
+    /// [crate::run] can be shared with the threads directly.
+    ///
+    /// Returns false, dropping `update`, if the window has closed.
+    pub fn update(&self, update: impl FnOnce(&mut Scene) + Send + 'static) -> bool {
+        if self.sender.send(Box::new(update)).is_err() {
+            return false;
+        }
+        if let Ok(waker) = self.waker.lock()
+            && let Some(proxy) = waker.as_ref()
+        {
+            let _ = proxy.send_event(());
+        }
+        true
+    }
+}
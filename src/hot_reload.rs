@@ -0,0 +1,80 @@
+//! Reloading of the WGSL shaders from the source tree while the window is open, enabled by the `hot-reload` feature
+//! in debug builds. The shader files are polled for modification, and a shader that fails to compile is reported on
+//! stderr while the last good pipelines keep drawing.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+/// Interval between checks of the shader files.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub(crate) struct ShaderWatcher {
+    // flat and texture shader files, with their modification time when last read
+    files: [(PathBuf, Option<SystemTime>); 2],
+}
+
+impl ShaderWatcher {
+    /// Watch the shader files of the source tree the crate was built from. The embedded shaders are their
+    /// current contents, so only later modifications count.
+    pub fn new() -> Self {
+        let file = |name: &str| {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("src/shader")
+                .join(name);
+            let modified = modified(&path);
+            (path, modified)
+        };
+        Self {
+            files: [file("flat.wgsl"), file("texture.wgsl")],
+        }
+    }
+
+    /// Sources of the flat and texture shaders whose file was modified since the last check, `None` for the others.
+    pub fn changed(&mut self) -> [Option<String>; 2] {
+        self.files.each_mut().map(|(path, last_modified)| {
+            let modified = modified(path);
+            if modified.is_none() || modified == *last_modified {
+                return None;
+            }
+            *last_modified = modified;
+            match std::fs::read_to_string(&*path) {
+                Ok(source) => Some(source),
+                Err(err) => {
+                    eprintln!("guiug: failed to read {}: {err}", path.display());
+                    None
+                }
+            }
+        })
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Compile the WGSL `source` and build pipelines from it with `build`. Returns `None` and prints the error if the
+/// shader or the pipelines fail validation.
+pub(crate) fn try_build<T>(
+    device: &wgpu::Device,
+    name: &str,
+    source: String,
+    build: impl FnOnce(&wgpu::ShaderModule) -> T,
+) -> Option<T> {
+    let scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let built = build(&shader);
+    match pollster::block_on(scope.pop()) {
+        None => Some(built),
+        Some(err) => {
+            eprintln!("guiug: keeping the previous {name} shader: {err}");
+            None
+        }
+    }
+}
@@ -1,75 +1,537 @@
 //! Declarative GUI library in Rust.
 //! Create [Guiug] object and call [run] with it.
 
+mod animation;
+pub mod color;
+#[cfg(feature = "cpu")]
+mod cpu;
+mod draw;
+mod error;
+mod event;
+mod gpu;
+mod handle;
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+mod hot_reload;
+#[cfg(feature = "pdf")]
+mod pdf;
 mod renderer;
 mod scene;
+mod svg;
+mod text;
 mod texture;
 mod types;
+mod window;
 
-pub use glam::Vec4;
-use glam::{IVec2, IVec3, UVec3};
-pub use scene::{Anchor, Node, NodeId, Position, Scene, Size};
-use std::sync::Arc;
+pub use animation::{AnimationId, Easing};
+pub use error::GuiugError;
+pub use event::{LINE_HEIGHT, Modifiers, MouseButton, UiEvent};
+use glam::Affine2;
+pub use glam::{Vec2, Vec4};
+pub use gpu::{Backend, DepthConfig, GpuConfig, PowerPreference};
+pub use handle::SceneHandle;
+pub use image;
+pub use scene::{
+    Anchor, BlendMode, CrossAlign, DEFAULT_MAX_DEPTH, FrameStats, Node, NodeId, Position, Scene,
+    SceneError, Size,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, mpsc},
+    time::Instant,
+};
+pub use text::FontError;
+pub use texture::{Filter, TextureError, TextureOptions};
 use types::Rect;
-use wgpu::{BindGroupDescriptor, BindGroupLayoutDescriptor, util::DeviceExt};
-
-use crate::types::Dimension;
+pub use types::{BoundingBox, Dimension};
+pub use wgpu;
+pub use window::{PresentMode, WindowConfig};
+pub use winit::keyboard::{Key, NamedKey};
 
 /// Interface for guiug application.
 ///
+/// `Guiug` is the builder of an application: it owns the [Scene] holding the nodes, and the textures
+/// registered with [Guiug::add_texture], whose encoded data is borrowed for `'a` and decoded when the window opens.
+/// Nodes are created through the builder, or on the scene itself with [Guiug::scene_mut].
+/// [run] takes the whole builder, and from then on the handlers receive the scene to change it.
+/// Textures added later with [Scene::add_texture_rgba8] are owned by the scene instead.
+///
 /// # Example
-/// ```
+/// ```no_run
 /// let mut guiug = guiug::Guiug::default();
 /// let root_node = guiug.layer_node(vec![]);
-/// guiug.set_root(root_node);
-/// guiug::run("awesome application", guiug);
+/// guiug.set_root(root_node).unwrap();
+/// guiug::run("awesome application", guiug).unwrap();
 /// ```
 #[derive(Default)]
 pub struct Guiug<'a> {
     scene: Scene,
     texture_info_manager: texture::TextureInfoManager<'a>,
+    event_handler: Option<event::EventHandler<'a>>,
+    update_handler: Option<event::UpdateHandler<'a>>,
+    render_handler: Option<event::RenderHandler<'a>>,
+    window_config: WindowConfig,
+    gpu_config: GpuConfig,
+    // additional windows opened by run, with their titles
+    windows: Vec<(String, Guiug<'a>)>,
+    // updates sent by the handles of the scene, created with the first one
+    updates: Option<(mpsc::Receiver<handle::Update>, SceneHandle)>,
 }
 
 impl<'a> Guiug<'a> {
+    /// Create guiug application whose scene has room for at least `capacity` nodes.
+    /// Use it when building thousands of nodes at startup to avoid rehashing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            scene: Scene::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
     /// Add texture to be loaded and used later. You can use the returned TextureId to construct texture node.
     pub fn add_texture(&mut self, texture_data: &'a [u8]) -> texture::TextureId {
-        self.texture_info_manager.add_texture_info(texture_data)
+        self.insert_texture(texture::TextureInfo::encoded(texture_data))
+    }
+
+    /// Add texture sampled with the given filter, e.g. [Filter::Nearest] for crisp pixel art.
+    pub fn add_texture_with_filter(
+        &mut self,
+        texture_data: &'a [u8],
+        filter: Filter,
+    ) -> texture::TextureId {
+        self.insert_texture(texture::TextureInfo {
+            options: TextureOptions {
+                filter,
+                ..Default::default()
+            },
+            ..texture::TextureInfo::encoded(texture_data)
+        })
+    }
+
+    /// Add texture with the given sampling settings, e.g. without mipmaps for icons drawn at their own size.
+    /// Fails if the options cannot be combined, see [TextureOptions::anisotropy].
+    pub fn add_texture_with_options(
+        &mut self,
+        texture_data: &'a [u8],
+        options: TextureOptions,
+    ) -> Result<texture::TextureId, TextureError> {
+        Ok(self.insert_texture(texture::TextureInfo::encoded(texture_data).with_options(options)?))
+    }
+
+    /// Like [Self::add_texture], but returns an error instead of drawing nothing when the data is not a supported image.
+    pub fn try_add_texture(
+        &mut self,
+        texture_data: &'a [u8],
+    ) -> Result<texture::TextureId, TextureError> {
+        Ok(self.insert_texture(texture::TextureInfo::try_encoded(texture_data)?))
+    }
+
+    /// Add texture read from an image file at runtime, instead of embedding it with `include_bytes!`.
+    /// The file is read and its format checked immediately; decoding happens when the window starts.
+    pub fn add_texture_from_path(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<texture::TextureId, TextureError> {
+        Ok(self.insert_texture(texture::TextureInfo::from_path(path)?))
+    }
+
+    /// Format detected from the header of a texture added as an encoded image, e.g. to log what a user-supplied file
+    /// turned out to be. `None` for unrecognized data, which fails with [TextureError::UnknownFormat] when loaded.
+    pub fn texture_format(&self, texture_id: texture::TextureId) -> Option<image::ImageFormat> {
+        self.texture_info_manager.get(texture_id)?.format()
+    }
+
+    /// Width and height in pixels of a texture. Same as [Scene::texture_size].
+    pub fn texture_size(&self, texture_id: texture::TextureId) -> Option<(u32, u32)> {
+        self.scene.texture_size(texture_id)
+    }
+
+    fn insert_texture(&mut self, texture_info: texture::TextureInfo<'a>) -> texture::TextureId {
+        let id = self.scene.next_texture_id();
+        // known before the window starts, for layout with Size::AspectOf
+        if let Some(size) = texture_info.size() {
+            self.scene.texture_sizes.insert(id, size);
+        }
+        self.texture_info_manager.insert(id, texture_info);
+        id
+    }
+
+    /// Set handler called with the scene for every mouse and keyboard event. The window redraws after the handler returns.
+    pub fn on_event(&mut self, handler: impl FnMut(&mut Scene, UiEvent) + 'a) {
+        self.event_handler = Some(Box::new(handler));
+    }
+
+    /// Set handler called once per frame before rendering, with the time in seconds since the last frame.
+    /// Use it for application logic and animation.
+    pub fn on_update(&mut self, handler: impl FnMut(&mut Scene, f32) + 'a) {
+        self.update_handler = Some(Box::new(handler));
+    }
+
+    /// Set handler drawing with raw wgpu into the window's render pass, after every node is drawn.
+    /// The pass targets the surface in its sRGB format (usually `Bgra8UnormSrgb` or `Rgba8UnormSrgb`) with the depth
+    /// buffer of [GpuConfig::depth], `Depth32Float` by default, cleared to 1.0 and the sample count of
+    /// [Scene::sample_count], so pipelines must use the same, and no depth-stencil state without a depth buffer.
+    /// Nodes are drawn with depths in 0~1 compared with `Less`, so drawing at depth 0 stays on top.
+    /// The scissor rect covers the whole window, and the pipeline and bind groups of the last node draw are left set.
+    /// The `wgpu` version must match; use the one re-exported as [wgpu].
+    pub fn on_render(
+        &mut self,
+        handler: impl FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::RenderPass<'_>) + 'a,
+    ) {
+        self.render_handler = Some(Box::new(handler));
+    }
+
+    /// Set initial size and behavior of the window opened by [run]. Default is a resizable 800x800 window.
+    pub fn set_window_config(&mut self, config: WindowConfig) {
+        self.window_config = config;
+    }
+
+    /// Open another window with its own scene, handlers and window config when [run] starts, e.g. a tool palette.
+    /// Every window draws with the GPU device set up with the gpu config of the application passed to [run];
+    /// the gpu config of `window` only sets its sample count, depth buffer, wireframe and culling.
+    /// Its textures are loaded into that window only.
+    /// Closing a window drops its scene, and [run] returns when the last one closes.
+    pub fn add_window(&mut self, title: &str, window: Guiug<'a>) {
+        self.windows.push((title.to_string(), window));
+    }
+
+    /// Set graphics API and adapter preference used by [run] and [Guiug::render_to_image].
+    /// Default is the platform's primary API on the high-performance adapter.
+    pub fn set_gpu_config(&mut self, config: GpuConfig) {
+        self.gpu_config = config;
+    }
+
+    /// Set color the screen is cleared with before drawing nodes. Default is opaque black.
+    pub fn set_background(&mut self, color: Vec4) {
+        self.scene.set_background(color);
+    }
+
+    /// Redraw every frame for a steady frame loop. When disabled (default), the window only redraws on
+    /// resize, input events, running animations, or [Scene::request_redraw].
+    pub fn set_continuous(&mut self, continuous: bool) {
+        self.scene.set_continuous(continuous);
+    }
+
+    /// Export the scene laid out on a `width` x `height` screen as an SVG document.
+    /// Textures are embedded as base64 data URIs. Shader effects are not reproduced.
+    pub fn to_svg(&self, width: i32, height: i32) -> String {
+        svg::render(
+            &self.scene,
+            Some(&self.texture_info_manager),
+            Dimension::new(width, height),
+        )
+    }
+
+    /// Render the scene laid out on a `width` x `height` screen to an image on the GPU, without opening a window.
+    /// Blocks until the pixels are read back.
+    pub fn render_to_image(&self, width: u32, height: u32) -> Result<image::RgbaImage, GuiugError> {
+        pollster::block_on(gpu::render_to_image(
+            &self.scene,
+            Some(&self.texture_info_manager),
+            self.gpu_config,
+            width,
+            height,
+        ))
+    }
+
+    /// Rasterize the scene laid out on a `width` x `height` screen on the CPU, without any GPU.
+    /// Textures are decoded and drawn. Shader effects are not reproduced.
+    #[cfg(feature = "cpu")]
+    pub fn render_to_image_cpu(&self, width: u32, height: u32) -> image::RgbaImage {
+        cpu::render(
+            &self.scene,
+            Some(&self.texture_info_manager),
+            Dimension::new(width as i32, height as i32),
+        )
+    }
+
+    /// Export the scene laid out on a `width` x `height` screen as a single page PDF document at `path`,
+    /// one point per pixel. Textures are embedded as images. Shader effects are not reproduced.
+    #[cfg(feature = "pdf")]
+    pub fn to_pdf(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        width: i32,
+        height: i32,
+    ) -> std::io::Result<()> {
+        let pdf = pdf::render(
+            &self.scene,
+            Some(&self.texture_info_manager),
+            Dimension::new(width, height),
+        );
+        std::fs::write(path, pdf)
+    }
+
+    /// Handle for other threads to update the scene once [run] takes it, see [SceneHandle::update].
+    /// Every call returns a handle to the same queue of updates.
+    pub fn scene_handle(&mut self) -> SceneHandle {
+        self.updates
+            .get_or_insert_with(|| {
+                let (handle, receiver) = SceneHandle::new();
+                (receiver, handle)
+            })
+            .1
+            .clone()
+    }
+
+    /// Access the scene directly, e.g. to start animations before running.
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
     }
 
     /// Set scene root. You have to set root in order to render anything on the screen. Root node will have same size as the screen.
-    pub fn set_root(&mut self, root_node: NodeId) {
+    /// Fails without changing the root if the node does not exist.
+    pub fn set_root(&mut self, root_node: NodeId) -> Result<(), SceneError> {
+        if self.scene.get_node(&root_node).is_none() {
+            return Err(SceneError::NotFound { node_id: root_node });
+        }
         self.scene.root_node = Some(root_node);
+        self.scene.dirty = true;
+        Ok(())
     }
 
     /// Create Layer node.
-    /// First one will be visible when overlapped.
+    /// Later children draw on top of earlier ones when overlapped, unless [Position::z_index] says otherwise.
+    /// Children must be existing nodes: missing ones are not checked here, draw nothing and are reported by
+    /// [Scene::validate], as are cycles, whose repeated node is skipped.
+    /// To add children later, use [Scene::layer_insert] which rejects missing nodes and cycles.
     pub fn layer_node(&mut self, inner: Vec<(Position, NodeId)>) -> NodeId {
-        let node = Node::Layer { inner };
+        let node = Node::Layer { inner, clip: false };
+        self.scene.insert_node(node)
+    }
+
+    /// Create Layer node which clips its descendants to its own rect.
+    /// Nested clipping layers clip to the intersection of their rects.
+    pub fn clip_layer_node(&mut self, inner: Vec<(Position, NodeId)>) -> NodeId {
+        let node = Node::Layer { inner, clip: true };
         self.scene.insert_node(node)
     }
 
     /// Create Rect node. It renders as solid rectangle. Color is RGBA0~1 Vec4.
+    /// Rect with alpha less than 1 is alpha blended over the nodes behind it.
     pub fn rect_node(&mut self, color: Vec4) -> NodeId {
         let node = Node::Rect { color };
         self.scene.insert_node(node)
     }
 
+    /// Create Rect node with rounded corners. `radius` resolves against the node's own size,
+    /// e.g. `Size::ParentHeight(0.5)` is half the node's height.
+    /// A radius larger than half the shorter side is reduced to it, so the node becomes a pill, or a circle if square.
+    /// The edges are anti-aliased, so the node is alpha blended like a translucent rect.
+    pub fn rounded_rect_node(&mut self, color: Vec4, radius: Size) -> NodeId {
+        let node = Node::RoundedRect { color, radius };
+        self.scene.insert_node(node)
+    }
+
+    /// Create Rect node with a solid border of `border` color drawn inside its edge, over the `fill` color.
+    /// `width` resolves against the node's own size, e.g. `Size::ParentWidth(0.1)` is a tenth of the node's width.
+    /// A width larger than half the shorter side is reduced to it, so the border covers the whole node.
+    pub fn bordered_rect_node(&mut self, fill: Vec4, border: Vec4, width: Size) -> NodeId {
+        let node = Node::BorderedRect {
+            fill,
+            border,
+            width,
+        };
+        self.scene.insert_node(node)
+    }
+
+    /// Create Gradient node. It renders as a rectangle whose color changes linearly from `start` to `end`.
+    /// `angle` is the direction in radians: 0 runs left to right, `PI / 2` top to bottom. The corners of the
+    /// node along the direction get exactly the `start` and `end` colors. Colors are interpolated in linear space.
+    /// If either color has alpha less than 1, the node is alpha blended like a translucent rect.
+    pub fn gradient_node(&mut self, start: Vec4, end: Vec4, angle: f32) -> NodeId {
+        let node = Node::Gradient { start, end, angle };
+        self.scene.insert_node(node)
+    }
+
+    /// Create Circle node. It renders as a solid ellipse filling the node's rect, so give it a square rect for a circle.
+    /// The edge is anti-aliased, so the node is alpha blended like a translucent rect.
+    pub fn circle_node(&mut self, color: Vec4) -> NodeId {
+        let node = Node::Circle { color };
+        self.scene.insert_node(node)
+    }
+
     /// Create texture node. It renders as rectangular image.
     /// To create texture, use [Self::add_texture]
+    /// Textures with a texel that is not fully opaque are alpha blended like translucent rects, back to front after the
+    /// opaque nodes, so that overlapping sprites show through each other. Opaque textures are depth tested.
     pub fn texture_node(&mut self, texture_id: texture::TextureId) -> NodeId {
-        let node = Node::Texture { texture_id };
+        self.texture_node_tinted(texture_id, Vec4::ONE)
+    }
+
+    /// Create texture node whose texels are multiplied by `tint`, e.g. gray to dim a disabled icon.
+    /// Tint is linear RGBA0~1 like node colors; white shows the texture unchanged.
+    pub fn texture_node_tinted(&mut self, texture_id: texture::TextureId, tint: Vec4) -> NodeId {
+        let node = Node::Texture {
+            texture_id,
+            tint,
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            flip_x: false,
+            flip_y: false,
+        };
+        self.scene.insert_node(node)
+    }
+
+    /// Create texture node showing only a region of the texture, e.g. one cell of a sprite sheet.
+    /// `uv_min` and `uv_max` are the top-left and bottom-right corners of the region in normalized texture
+    /// coordinates, from (0, 0) at the top-left of the texture to (1, 1) at its bottom-right.
+    /// Packing many icons into one texture lets them be drawn with a single draw call.
+    pub fn sprite_node(
+        &mut self,
+        texture_id: texture::TextureId,
+        uv_min: Vec2,
+        uv_max: Vec2,
+    ) -> NodeId {
+        let node = Node::Texture {
+            texture_id,
+            tint: Vec4::ONE,
+            uv_min,
+            uv_max,
+            flip_x: false,
+            flip_y: false,
+        };
+        self.scene.insert_node(node)
+    }
+
+    /// Create nine-patch node, for resizable buttons and panels. The texture is split into nine regions by
+    /// `insets`, the top, right, bottom and left border widths: corners are drawn unscaled, edges stretch along
+    /// their length, and the center stretches both ways.
+    /// Insets resolve against the node's own size, and cover as many texture pixels as screen pixels.
+    /// When the node is smaller than two opposite insets combined, both are shrunk proportionally to fit,
+    /// squashing the corners and leaving out the edge between them.
+    /// The texture's size must be known, see [Scene::texture_size]; otherwise the whole texture is stretched.
+    pub fn nine_patch_node(&mut self, texture_id: texture::TextureId, insets: [Size; 4]) -> NodeId {
+        let node = Node::NinePatch { texture_id, insets };
+        self.scene.insert_node(node)
+    }
+
+    /// Create row node, which stacks its children from top to bottom with the given heights.
+    /// `gap` is inserted between consecutive children, and is taken from the height left for [Size::Weight] children.
+    /// It resolves against the row's own size. Use [Size::ZERO] for no gap.
+    /// `align` places the children horizontally, see [CrossAlign] for children that keep their aspect ratio.
+    ///
+    /// # Example
+    /// ```
+    /// use guiug::{CrossAlign, Dimension, Guiug, Size, Vec4};
+    ///
+    /// let mut guiug = Guiug::default();
+    /// let children: Vec<_> = (0..3)
+    ///     .map(|_| (Size::Weight(1.0), guiug.rect_node(Vec4::ONE)))
+    ///     .collect();
+    /// let ids: Vec<_> = children.iter().map(|(_, id)| *id).collect();
+    /// let row = guiug.row_node(children, Size::Pixel(10), CrossAlign::Stretch);
+    /// guiug.set_root(row).unwrap();
+    ///
+    /// // 320 pixels minus two gaps, split in three
+    /// let screen = Dimension::new(100, 320);
+    /// let rects: Vec<_> = ids
+    ///     .iter()
+    ///     .map(|id| guiug.scene_mut().layout_rect(*id, screen).unwrap())
+    ///     .collect();
+    /// assert_eq!(rects.iter().map(|rect| rect.y).collect::<Vec<_>>(), [0, 110, 220]);
+    /// assert!(rects.iter().all(|rect| rect.h == 100));
+    /// ```
+    pub fn row_node(&mut self, inner: Vec<(Size, NodeId)>, gap: Size, align: CrossAlign) -> NodeId {
+        let node = Node::Row { inner, gap, align };
+        self.scene.insert_node(node)
+    }
+
+    /// Create column node, which places its children from left to right with the given widths.
+    /// `gap` is inserted between consecutive children, and is taken from the width left for [Size::Weight] children.
+    /// It resolves against the column's own size. Use [Size::ZERO] for no gap.
+    /// `align` places the children vertically, see [CrossAlign] for children that keep their aspect ratio.
+    pub fn column_node(
+        &mut self,
+        inner: Vec<(Size, NodeId)>,
+        gap: Size,
+        align: CrossAlign,
+    ) -> NodeId {
+        let node = Node::Column { inner, gap, align };
+        self.scene.insert_node(node)
+    }
+
+    /// Create scroll node, which lays `child` out in its own rect moved up by the scroll offset, clipping it to its rect.
+    /// The content height spans from the top of the child to the bottom of its lowest descendant, e.g. a Row whose
+    /// fixed heights add up to more than the scroll node's height. The offset is kept between 0 and the content
+    /// height minus the node's height.
+    /// Set the offset with [Scene::set_scroll_offset]; the mouse wheel scrolls the scroll node under the cursor.
+    pub fn scroll_node(&mut self, child: NodeId) -> NodeId {
+        let node = Node::Scroll { child };
         self.scene.insert_node(node)
     }
 
-    /// Create row node.
-    pub fn row_node(&mut self, inner: Vec<(Size, NodeId)>) -> NodeId {
-        let node = Node::Row { inner };
+    /// Create padding node, which lays `child` out inside its own rect shrunk by `insets`, the left, top, right
+    /// and bottom spacing. Insets resolve against the padding node's own size, e.g. `Size::ParentWidth(0.1)` is
+    /// a tenth of its width. Negative insets let the child overflow the node; wrap it in [Self::clip_layer_node]
+    /// to cut the overflow off.
+    pub fn padding_node(&mut self, insets: [Size; 4], child: NodeId) -> NodeId {
+        let node = Node::Padding { insets, child };
         self.scene.insert_node(node)
     }
 
-    /// Create column node.
-    pub fn column_node(&mut self, inner: Vec<(Size, NodeId)>) -> NodeId {
-        let node = Node::Column { inner };
+    /// Create opacity group node, which lays `child` out in its own rect and composites the whole subtree at `alpha`.
+    /// Unlike lowering the alpha of each node, overlapping descendants do not show through each other: the subtree
+    /// is drawn into an offscreen target first, then blended onto the screen as one image.
+    ///
+    /// Each group costs a screen-sized color and depth target, an extra render pass per frame and the draw calls of
+    /// its subtree in that pass. Prefer the alpha of the node colors when the faded nodes do not overlap, e.g. a
+    /// single rect or a line of text. Blend modes inside the group combine with the group's content only.
+    /// Update the alpha with [Scene::set_group_opacity].
+    pub fn group_opacity_node(&mut self, alpha: f32, child: NodeId) -> NodeId {
+        let node = Node::Opacity { alpha, child };
+        self.scene.insert_node(node)
+    }
+
+    /// Create line node, drawing a polyline through `points` in `color`, e.g. a chart or a connector between nodes.
+    /// Each point is the (x, y) offset from the node's top-left corner, resolved against the node's own size, so
+    /// `(Size::ParentWidth(1.0), Size::ParentHeight(1.0))` is its bottom-right corner. `width` resolves the same way.
+    /// Segments have flat ends and are joined with a bevel. Repeated points are skipped, and a line with fewer than
+    /// two distinct points or no width draws nothing. The line is anti-aliased, so it is alpha blended; translucent
+    /// lines show where segments overlap on the inside of turns, unless drawn in [Self::group_opacity_node].
+    /// Like other nodes, it is hit anywhere in its rect.
+    pub fn line_node(&mut self, points: Vec<(Size, Size)>, width: Size, color: Vec4) -> NodeId {
+        let node = Node::Line {
+            points,
+            width,
+            color,
+        };
+        self.scene.insert_node(node)
+    }
+
+    /// Create shadow node, which lays `child` out in its own rect over a soft drop shadow of the rect, e.g. under a
+    /// card or a dialog. The shadow is the node's rect moved by `offset` (x, y), fading out from `color` to transparent
+    /// across `blur` pixels on each side of its edge, so it is half transparent on the edge itself.
+    /// `offset` and `blur` resolve against the node's own size, so the shadow follows the child's resolved size.
+    /// The shadow is drawn with a soft edge in the flat shader, without a blur pass. It is not hit.
+    pub fn shadow_node(
+        &mut self,
+        child: NodeId,
+        offset: (Size, Size),
+        blur: Size,
+        color: Vec4,
+    ) -> NodeId {
+        let node = Node::Shadow {
+            child,
+            offset,
+            blur,
+            color,
+        };
+        self.scene.insert_node(node)
+    }
+
+    /// Create text node, drawing `text` in `color` with the font set with [Scene::set_font].
+    /// Lines are broken at `\n` only and start at the node's left edge; the first line starts at its top edge.
+    /// `size` is the line height from the top of the tallest to the bottom of the lowest glyph, and resolves against
+    /// the node's own size, e.g. `Size::ParentHeight(1.0)` fills the node's height with one line.
+    /// Glyphs are anti-aliased, so they are alpha blended like translucent rects.
+    /// All glyphs share one atlas texture, so the text of every node is drawn with a single draw call per clipping layer.
+    pub fn text_node(&mut self, text: &str, size: Size, color: Vec4) -> NodeId {
+        self.scene.add_glyphs(text);
+        let node = Node::Text {
+            text: text.to_owned(),
+            size,
+            color,
+        };
         self.scene.insert_node(node)
     }
 
@@ -80,18 +542,76 @@ impl<'a> Guiug<'a> {
     }
 }
 
-/// Run the given guiug application.
-/// This function will not return until the window closes.
+/// Rasterize the scene laid out on a `width` x `height` screen on the CPU, for servers and CI without any GPU.
+/// Only textures added with [Scene::add_texture_rgba8] are drawn, as the builder owns the others; use [Guiug::render_to_image_cpu] to draw them too.
+/// Shader effects are not reproduced.
+#[cfg(feature = "cpu")]
+pub fn render_to_image_cpu(scene: &Scene, width: u32, height: u32) -> image::RgbaImage {
+    cpu::render(scene, None, Dimension::new(width as i32, height as i32))
+}
+
+/// Render the scene laid out on a `width` x `height` screen to an image on the GPU, without opening a window.
+/// Blocks until the pixels are read back. Useful for screenshot tests and thumbnails.
+/// Only textures added with [Scene::add_texture_rgba8] are drawn, as the builder owns the others; use [Guiug::render_to_image] to draw them too.
+pub fn render_to_image(
+    scene: &Scene,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage, GuiugError> {
+    pollster::block_on(gpu::render_to_image(
+        scene,
+        None,
+        GpuConfig::default(),
+        width,
+        height,
+    ))
+}
+
+/// Run the given guiug application, with the windows added by [Guiug::add_window].
+/// This function will not return until every window closes, or the GPU runs out of memory for the next frame.
+/// Fails if the window or the GPU device cannot be set up, e.g. on a machine without a display or a compatible GPU.
 /// * `title` - window title
 /// * `guiug` - guiug application to run
-pub fn run(title: &str, guiug: Guiug) {
-    let event_loop = winit::event_loop::EventLoop::new().unwrap();
+pub fn run(title: &str, guiug: Guiug) -> Result<(), GuiugError> {
+    let event_loop = winit::event_loop::EventLoop::new()?;
     let mut app = Handler {
-        state: None,
-        guiug: Some(guiug),
-        title,
+        proxy: event_loop.create_proxy(),
+        states: HashMap::new(),
+        pending: vec![(title.to_string(), guiug)],
+        device: None,
+        error: None,
     };
-    event_loop.run_app(&mut app).unwrap();
+    event_loop.run_app(&mut app)?;
+    app.error.map_or(Ok(()), Err)
+}
+
+/// Run the given guiug application, calling `event_handler` for every mouse and keyboard event.
+/// The handler can mutate the scene, and the window redraws after it returns.
+/// Combine it with [Scene::node_at] to find the node under the cursor.
+/// * `title` - window title
+/// * `guiug` - guiug application to run
+/// * `event_handler` - called with the scene and the event
+pub fn run_with<'a>(
+    title: &str,
+    mut guiug: Guiug<'a>,
+    event_handler: impl FnMut(&mut Scene, UiEvent) + 'a,
+) -> Result<(), GuiugError> {
+    guiug.on_event(event_handler);
+    run(title, guiug)
+}
+
+/// Run the given guiug application, calling `render_handler` to draw with raw wgpu after every node is drawn.
+/// See [Guiug::on_render] for the formats of the render pass.
+/// * `title` - window title
+/// * `guiug` - guiug application to run
+/// * `render_handler` - called with the device, the queue and the window's render pass every frame
+pub fn run_with_raw<'a>(
+    title: &str,
+    mut guiug: Guiug<'a>,
+    render_handler: impl FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::RenderPass<'_>) + 'a,
+) -> Result<(), GuiugError> {
+    guiug.on_render(render_handler);
+    run(title, guiug)
 }
 
 struct State<'a> {
@@ -103,50 +623,44 @@ struct State<'a> {
 
     // wgpu-related
     surface: wgpu::Surface<'a>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     surface_configuration: wgpu::SurfaceConfiguration,
-    depth_texture_view: wgpu::TextureView,
+    // `None` when drawing without a depth buffer
+    depth_texture_view: Option<wgpu::TextureView>,
+    gpu: gpu::Gpu,
 
-    flat_renderer: renderer::FlatRenderer,
-    texture_renderer: renderer::TextureRenderer,
-    screen_uniform_buffer: wgpu::Buffer,
-    screen_uniform_bind_group: wgpu::BindGroup,
+    last_frame: Instant,
 
-    texture_manager: texture::TextureManager,
+    // input
+    event_handler: Option<event::EventHandler<'a>>,
+    update_handler: Option<event::UpdateHandler<'a>>,
+    render_handler: Option<event::RenderHandler<'a>>,
+    cursor_position: (i32, i32),
+    modifiers: event::Modifiers,
+    updates: Option<mpsc::Receiver<handle::Update>>,
+
+    // The window has a zero-size surface, e.g. while minimized. The surface cannot be configured nor drawn to.
+    minimized: bool,
 }
 
 impl<'a> State<'a> {
-    async fn new(window: Arc<winit::window::Window>, guiug: Guiug<'a>) -> Self {
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window.clone()).unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default())
-            .await
-            .unwrap();
+    fn new(
+        window: Arc<winit::window::Window>,
+        guiug: Guiug<'a>,
+        shared: &gpu::SharedDevice,
+        surface: wgpu::Surface<'a>,
+    ) -> Result<Self, GuiugError> {
+        let adapter = &shared.adapter;
+        let device = shared.device.clone();
 
         // surface
-        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_caps = surface.get_capabilities(adapter);
         let surface_format = surface_caps
             .formats
             .iter()
             .find(|format| format.is_srgb())
+            .or(surface_caps.formats.first())
             .copied()
-            .unwrap_or(surface_caps.formats[0]);
+            .ok_or(GuiugError::UnsupportedSurface)?;
 
         let size = window.inner_size();
         let surface_configuration = wgpu::SurfaceConfiguration {
@@ -154,322 +668,1041 @@ impl<'a> State<'a> {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: guiug
+                .window_config
+                .present_mode
+                .select(&surface_caps.present_modes),
             desired_maximum_frame_latency: 2,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
-        surface.configure(&device, &surface_configuration);
-
-        // texture manager
-        let mut texture_manager = texture::TextureManager::new(&device);
-        texture_manager.load(&device, &queue, &guiug.texture_info_manager);
-
-        // screen uniform
-        let screen_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-
-        let screen_size = UVec3::new(size.width, size.height, 0);
-        let screen_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("screen uniform buffer"),
-            contents: bytemuck::cast_slice(&[screen_size]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+        let minimized = size.width == 0 || size.height == 0;
+        if !minimized {
+            surface.configure(&device, &surface_configuration);
+        }
 
-        let screen_uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("screen uniform bind group"),
-            layout: &screen_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &screen_uniform_buffer,
-                    offset: 0,
-                    size: None,
-                }),
-            }],
+        let pipeline_config = guiug
+            .gpu_config
+            .pipeline_config(adapter, &device, surface_format);
+        let sample_count = pipeline_config.sample_count;
+        let depth_texture_view = pipeline_config.depth_format.map(|format| {
+            texture::create_depth_texture(
+                &device,
+                size.width.max(1),
+                size.height.max(1),
+                sample_count,
+                format,
+            )
         });
 
-        // renderer
-        let flat_renderer =
-            renderer::FlatRenderer::new(&device, surface_format, &screen_bind_group_layout);
+        let mut gpu = gpu::Gpu::new(device, shared.queue.clone(), pipeline_config);
+        let mut scene = guiug.scene;
+        scene.fallback_adapter = shared.fallback_adapter;
+        scene.sample_count = sample_count;
+        scene.scale_factor = window.scale_factor() as f32;
+        scene.screen_size = Dimension::new(size.width as i32, size.height as i32);
+        scene.texture_errors = gpu.load_textures(&scene, Some(&guiug.texture_info_manager));
+        scene.pending_textures.clear();
 
-        let texture_renderer = renderer::TextureRenderer::new(
-            &device,
-            surface_format,
-            &screen_bind_group_layout,
-            &texture_manager.bind_group_layout,
-        );
-
-        let depth_texture_view = texture::create_depth_texture(&device, &surface_configuration);
-
-        Self {
-            scene: guiug.scene,
+        Ok(Self {
+            scene,
 
             window,
             surface,
-            device,
-            queue,
             surface_configuration,
-            flat_renderer,
-            texture_renderer,
-            screen_uniform_buffer,
-            screen_uniform_bind_group,
-
-            texture_manager,
             depth_texture_view,
+            gpu,
+
+            last_frame: Instant::now(),
+
+            event_handler: guiug.event_handler,
+            update_handler: guiug.update_handler,
+            render_handler: guiug.render_handler,
+            cursor_position: (0, 0),
+            modifiers: event::Modifiers::default(),
+            updates: guiug.updates.map(|(receiver, _)| receiver),
+            minimized,
+        })
+    }
+
+    // Deliver the event to the user handler and redraw with the updated scene.
+    fn handle_event(&mut self, event: UiEvent) {
+        if let Some(event_handler) = &mut self.event_handler {
+            event_handler(&mut self.scene, event);
+            self.window.request_redraw();
         }
     }
 
-    fn update(&mut self) {}
+    fn update(&mut self) {
+        if let Some(updates) = &self.updates {
+            for update in updates.try_iter() {
+                update(&mut self.scene);
+            }
+        }
+
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        if let Some(update_handler) = &mut self.update_handler {
+            update_handler(&mut self.scene, dt);
+        }
+        self.scene.advance_animations(dt);
+    }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.minimized {
+            return Ok(());
+        }
+        self.gpu.upload_pending_textures(&mut self.scene);
+
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    depth_slice: None,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                ..Default::default()
-            });
-
-            let screen_size = Dimension::new(
-                self.surface_configuration.width as i32,
-                self.surface_configuration.height as i32,
-            );
-            let visitor = NodeVisitor::visit(screen_size, &self.scene);
-
-            self.queue.write_buffer(
-                &self.screen_uniform_buffer,
-                0,
-                bytemuck::cast_slice(&[UVec3::new(
-                    screen_size.width as u32,
-                    screen_size.height as u32,
-                    visitor.z_index as u32,
-                )]),
-            );
-
-            // bind screen uniform
-            render_pass.set_bind_group(0, &self.screen_uniform_bind_group, &[]);
-
-            // Flat rendering
-            self.flat_renderer
-                .draw(&mut render_pass, &self.queue, visitor.rect_instances);
+        let screen_size = Dimension::new(
+            self.surface_configuration.width as i32,
+            self.surface_configuration.height as i32,
+        );
+        self.scene.frame_stats = self.gpu.draw(
+            &self.scene,
+            &view,
+            self.depth_texture_view.as_ref(),
+            screen_size,
+            self.render_handler.as_mut(),
+        );
 
-            // Texture rendering
-            self.texture_renderer.draw(
-                &mut render_pass,
-                &self.queue,
-                &self.texture_manager,
-                visitor.texture_instances,
-            );
+        if let Some(path) = self.scene.screenshot_request.take() {
+            let result = self
+                .gpu
+                .capture(&self.scene, screen_size, self.render_handler.as_mut())
+                .and_then(|image| Ok(image.save_with_format(&path, image::ImageFormat::Png)?));
+            if let Err(err) = result {
+                self.scene.screenshot_errors.push((path, err));
+            }
         }
-
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.scene.dirty = false;
 
         self.window.pre_present_notify();
         output.present();
 
-        // if you want to render every frame
-        // self.window.request_redraw();
         Ok(())
     }
 
+    // A zero size pauses rendering until a non-zero size arrives.
     fn resize(&mut self, width: u32, height: u32) {
+        self.minimized = width == 0 || height == 0;
+        if self.minimized {
+            return;
+        }
         self.surface_configuration.width = width;
         self.surface_configuration.height = height;
+        self.scene.screen_size = Dimension::new(width as i32, height as i32);
         self.surface
-            .configure(&self.device, &self.surface_configuration);
+            .configure(&self.gpu.device, &self.surface_configuration);
 
-        self.depth_texture_view =
-            texture::create_depth_texture(&self.device, &self.surface_configuration);
+        self.depth_texture_view = self.gpu.config.depth_format.map(|format| {
+            texture::create_depth_texture(
+                &self.gpu.device,
+                width,
+                height,
+                self.gpu.config.sample_count,
+                format,
+            )
+        });
     }
 }
 
 pub(crate) struct NodeVisitor {
     screen_size: Dimension,
-    rect_instances: Vec<renderer::FlatInstance>,
-    texture_instances: Vec<renderer::TextureInstance>,
+    // drawn primitives in traversal order
+    primitives: Vec<draw::Primitive>,
+    // draw lists of the opacity groups, by the index of their DrawCommand::Group
+    groups: Vec<draw::DrawList>,
+    // traversal order. After visiting, number of depth levels, one per drawn primitive.
     z_index: i32,
+    // accumulated Position::z_index of the current subtree
+    layer: i32,
+    // (layer, z_index) of every drawn primitive. The index is the key temporarily stored in instance z until depth is resolved.
+    depth_keys: Vec<(i32, i32)>,
+    // scissor rect of the nearest clipping ancestor
+    clip: Option<Rect>,
+    // rotation of the current subtree, composed over its rotated ancestors. `None` if nothing is rotated.
+    transform: Option<Affine2>,
+    // blend mode of the current subtree, set by its nearest ancestor with one
+    blend: BlendMode,
+    // visible rect and depth of every drawn node, for hit-testing
+    drawn_nodes: Vec<(NodeId, Rect, i32)>,
+    // resolved rect of every visited node, in traversal order
+    layout_rects: Vec<(NodeId, Rect)>,
+    // visible rect and max scroll offset of every Scroll node, in traversal order
+    scroll_ranges: Vec<(NodeId, Rect, i32)>,
+    // nodes on the path from the root to the current node
+    ancestors: HashSet<NodeId>,
+    // problems found in the scene. The offending subtrees are skipped.
+    errors: Vec<SceneError>,
 }
 
 impl NodeVisitor {
-    pub fn visit(screen_size: Dimension, scene: &Scene) -> Self {
-        let mut visitor = Self {
+    fn new(screen_size: Dimension) -> Self {
+        Self {
             screen_size,
-            rect_instances: Vec::new(),
-            texture_instances: Vec::new(),
+            primitives: Vec::new(),
+            groups: Vec::new(),
             z_index: 0,
-        };
+            layer: 0,
+            depth_keys: Vec::new(),
+            clip: None,
+            transform: None,
+            blend: BlendMode::Replace,
+            drawn_nodes: Vec::new(),
+            layout_rects: Vec::new(),
+            scroll_ranges: Vec::new(),
+            ancestors: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn visit(screen_size: Dimension, scene: &Scene) -> Self {
+        let mut visitor = Self::new(screen_size);
         if let Some(root_node) = scene.root_node {
             let screen_rect = Rect::new(0, 0, screen_size.width, screen_size.height);
 
             visitor.do_visit(scene, root_node, screen_rect);
         }
+        visitor.resolve_depth();
+        if scene.color_space == color::ColorSpace::Srgb {
+            let group_commands = visitor
+                .groups
+                .iter_mut()
+                .flat_map(|group| &mut group.commands);
+            visitor
+                .primitives
+                .iter_mut()
+                .map(|(_, _, _, command)| command)
+                .chain(group_commands)
+                .for_each(draw::DrawCommand::decode_srgb);
+        }
         visitor
     }
 
+    fn depth_key(&mut self) -> i32 {
+        self.depth_keys.push((self.layer, self.z_index));
+        self.depth_keys.len() as i32 - 1
+    }
+
+    // Replace depth keys in instances with depth ranks, nearest first.
+    // Higher layer comes first, and later traversal order comes first within the same layer, so that later-declared
+    // nodes draw on top like a painter's algorithm.
+    // Every primitive gets its own rank, even when it shares the layer and traversal order with another: the later
+    // pushed one is nearer. Coplanar primitives never z-fight and their order is stable from frame to frame.
+    fn resolve_depth(&mut self) {
+        let mut keys: Vec<usize> = (0..self.depth_keys.len()).collect();
+        keys.sort_by_key(|key| {
+            let (layer, order) = self.depth_keys[*key];
+            (
+                std::cmp::Reverse(layer),
+                std::cmp::Reverse(order),
+                std::cmp::Reverse(*key),
+            )
+        });
+
+        let mut depth = vec![0; keys.len()];
+        for (rank, key) in keys.iter().enumerate() {
+            depth[*key] = rank as i32;
+        }
+
+        for (_, _, _, command) in self.primitives.iter_mut() {
+            if let Some(depth_key) = command.depth_mut() {
+                *depth_key = depth[*depth_key as usize];
+            }
+        }
+        for (_, _, depth_key) in self.drawn_nodes.iter_mut() {
+            *depth_key = depth[*depth_key as usize];
+        }
+        self.z_index = keys.len() as i32;
+    }
+
+    // Primitives entirely outside the screen are culled. Partially visible ones are drawn whole and clipped by the GPU.
+    fn push_primitive(&mut self, command: draw::DrawCommand) {
+        if let Some(rect) = command.rect()
+            && !self.on_screen(rect)
+        {
+            return;
+        }
+        self.primitives
+            .push((self.clip, self.transform, self.blend, command));
+    }
+
+    // Whether the rect, moved by the current transform, overlaps the screen.
+    fn on_screen(&self, rect: Rect) -> bool {
+        let bounds = match self.transform {
+            None => rect,
+            Some(transform) => {
+                let (x, y) = (rect.x as f32, rect.y as f32);
+                let (right, bottom) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+                let corners = [(x, y), (right, y), (x, bottom), (right, bottom)]
+                    .map(|(x, y)| transform.transform_point2(Vec2::new(x, y)));
+                let min = corners.into_iter().reduce(Vec2::min).unwrap_or_default();
+                let max = corners.into_iter().reduce(Vec2::max).unwrap_or_default();
+                Rect::new(
+                    min.x.floor() as i32,
+                    min.y.floor() as i32,
+                    (max.x.ceil() - min.x.floor()) as i32,
+                    (max.y.ceil() - min.y.floor()) as i32,
+                )
+            }
+        };
+        let screen = Rect::new(0, 0, self.screen_size.width, self.screen_size.height);
+        let visible = bounds.intersect(screen);
+        visible.w > 0 && visible.h > 0
+    }
+
+    // Height of the subtree laid out in `rect`, from the top of `rect` to the bottom of its lowest node.
+    // The subtree is laid out by a separate visitor, so nothing is drawn.
+    fn content_height(&self, scene: &Scene, node_id: NodeId, rect: Rect) -> i32 {
+        let mut measure = Self::new(self.screen_size);
+        measure.ancestors = self.ancestors.clone();
+        measure.do_visit(scene, node_id, rect);
+        let bottom = measure
+            .layout_rects
+            .iter()
+            .map(|(_, rect)| rect.y + rect.h)
+            .max()
+            .unwrap_or(rect.y + rect.h);
+        bottom - rect.y
+    }
+
+    fn add_drawn_node(&mut self, node_id: NodeId, rect: Rect, depth_key: i32) {
+        let visible_rect = self.clip.map_or(rect, |clip| clip.intersect(rect));
+        self.drawn_nodes.push((node_id, visible_rect, depth_key));
+    }
+
+    // Outline the node's rect in a color of its kind, above every node and ignoring clipping layers.
+    fn push_debug_bounds(&mut self, node: &Node, rect: Rect) {
+        let color = match node {
+            Node::Layer { .. }
+            | Node::Scroll { .. }
+            | Node::Padding { .. }
+            | Node::Opacity { .. }
+            | Node::Shadow { .. } => Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Node::Row { .. } => Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Node::Column { .. } => Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Node::Texture { .. } | Node::NinePatch { .. } => Vec4::new(1.0, 0.0, 1.0, 1.0),
+            Node::Empty => Vec4::new(0.5, 0.5, 0.5, 1.0),
+            _ => Vec4::new(1.0, 1.0, 0.0, 1.0),
+        };
+        let (layer, clip, blend) = (self.layer, self.clip, self.blend);
+        self.layer = i32::MAX;
+        self.clip = None;
+        self.blend = BlendMode::Replace;
+        let depth_key = self.depth_key();
+        // the space reserved by empty nodes is otherwise invisible
+        if let Node::Empty = node {
+            self.push_primitive(draw::DrawCommand::Checkerboard {
+                rect,
+                depth: depth_key,
+                color: color.with_w(0.25),
+                cell: 8,
+            });
+        }
+        self.push_primitive(draw::DrawCommand::FilledRect {
+            rect,
+            depth: depth_key,
+            color: Vec4::ZERO,
+            radius: 0,
+            border: Some(draw::Border { width: 1, color }),
+        });
+        self.layer = layer;
+        self.clip = clip;
+        self.blend = blend;
+    }
+
+    // radius and border width are clamped to half the shorter side
+    fn push_rect(
+        &mut self,
+        node_id: NodeId,
+        rect: Rect,
+        color: Vec4,
+        radius: i32,
+        border: Option<(i32, Vec4)>,
+    ) {
+        let depth_key = self.depth_key();
+        self.add_drawn_node(node_id, rect, depth_key);
+        let max = rect.w.min(rect.h).max(0) / 2;
+        let border = border
+            .map(|(width, color)| draw::Border {
+                width: width.clamp(0, max),
+                color,
+            })
+            .filter(|border| border.width > 0);
+        self.push_primitive(draw::DrawCommand::FilledRect {
+            rect,
+            depth: depth_key,
+            color,
+            radius: radius.clamp(0, max),
+            border,
+        });
+    }
+
+    // Draw the subtree of an opacity group as its own draw list, composited as one primitive at the group's depth.
+    // The subtree keeps the clip, transform and blend mode of the group, so its list is in screen coordinates.
+    fn visit_group(&mut self, scene: &Scene, alpha: f32, child: NodeId, rect: Rect) {
+        let index = self.groups.len();
+        self.groups.push(draw::DrawList::default());
+        let mut group = Self::new(self.screen_size);
+        group.clip = self.clip;
+        group.transform = self.transform;
+        group.blend = self.blend;
+        group.ancestors = std::mem::take(&mut self.ancestors);
+        group.groups = std::mem::take(&mut self.groups);
+        group.do_visit(scene, child, rect);
+        group.resolve_depth();
+        self.ancestors = std::mem::take(&mut group.ancestors);
+        self.groups = std::mem::take(&mut group.groups);
+
+        // the group's nodes are hit in their order within the group, nearest first
+        let depth_key = self.depth_key();
+        group.drawn_nodes.sort_by_key(|(_, _, depth)| *depth);
+        self.drawn_nodes.extend(
+            group
+                .drawn_nodes
+                .into_iter()
+                .map(|(node_id, rect, _)| (node_id, rect, depth_key)),
+        );
+        self.layout_rects.append(&mut group.layout_rects);
+        self.scroll_ranges.append(&mut group.scroll_ranges);
+        self.errors.append(&mut group.errors);
+
+        if group.primitives.is_empty() {
+            return;
+        }
+        self.groups[index] = draw::DrawList::new(group.primitives, group.z_index);
+        let screen = Rect::new(0, 0, self.screen_size.width, self.screen_size.height);
+        self.primitives.push((
+            None,
+            None,
+            BlendMode::Replace,
+            draw::DrawCommand::Group {
+                rect: screen,
+                depth: depth_key,
+                index,
+                alpha,
+            },
+        ));
+    }
+
     pub fn do_visit(&mut self, scene: &Scene, node_id: NodeId, rect: Rect) {
+        // Truncate the traversal instead of overflowing the stack on malformed scenes.
+        if self.ancestors.len() >= scene.max_depth {
+            self.errors.push(SceneError::TooDeep {
+                node_id,
+                max_depth: scene.max_depth,
+            });
+            return;
+        }
+        // hidden subtrees are neither drawn, laid out nor hit-tested
+        if scene.hidden.contains(&node_id) {
+            return;
+        }
+        if scene.get_node(&node_id).is_none() {
+            self.errors.push(SceneError::NotFound { node_id });
+            return;
+        }
+        if !self.ancestors.insert(node_id) {
+            self.errors.push(SceneError::Cycle { node_id });
+            return;
+        }
+        self.layout_rects.push((node_id, rect));
+
+        // rotate the node and its descendants about the node's center
+        let parent_transform = self.transform;
+        if let Some(angle) = scene.rotations.get(&node_id) {
+            let center = Vec2::new(
+                rect.x as f32 + rect.w as f32 / 2.0,
+                rect.y as f32 + rect.h as f32 / 2.0,
+            );
+            let rotation = Affine2::from_translation(center)
+                * Affine2::from_angle(*angle)
+                * Affine2::from_translation(-center);
+            self.transform = Some(parent_transform.map_or(rotation, |parent| parent * rotation));
+        }
+        let parent_blend = self.blend;
+        if let Some(blend) = scene.blend_modes.get(&node_id) {
+            self.blend = *blend;
+        }
+
+        if scene.debug_bounds
+            && let Some(node) = scene.get_node(&node_id)
+        {
+            self.push_debug_bounds(node, rect);
+        }
+
         if let Some(node) = scene.get_node(&node_id) {
             match node {
-                Node::Layer { inner } => {
+                Node::Layer { inner, clip } => {
+                    let parent_clip = self.clip;
+                    if *clip {
+                        self.clip = Some(parent_clip.map_or(rect, |parent| parent.intersect(rect)));
+                    }
                     for (position, child_node_id) in inner {
-                        let child_rect = position.apply(rect, self.screen_size);
+                        let child_rect =
+                            position.apply(rect, self.screen_size, scene, *child_node_id);
+                        let parent_layer = self.layer;
+                        self.layer += position.z_index;
                         self.do_visit(scene, *child_node_id, child_rect);
+                        self.layer = parent_layer;
                         self.z_index += 1;
                     }
+                    self.clip = parent_clip;
                 }
-                Node::Row { inner } => {
-                    let mut total_size = rect.h;
+                Node::Row { inner, gap, align } => {
+                    let inner = scene.visible_children(inner);
+                    // heights. Size::AspectOf is derived from the row width, and Size::FitContent from the child's content height.
+                    let screen_size = self.screen_size;
+                    let resolve = |size: &Size, child_node_id: NodeId| match size.aspect(scene) {
+                        Some(aspect) => aspect.height_for(rect.w),
+                        None if matches!(size, Size::FitContent) => scene
+                            .content_size(child_node_id, rect.dimension(), screen_size)
+                            .map_or(0, |content| content.height),
+                        None => size.resolve(rect.dimension(), screen_size, scene.scale_factor),
+                    };
+                    let gap = gap.resolve(rect.dimension(), screen_size, scene.scale_factor);
+                    let mut total_size = rect.h - gap * (inner.len() as i32 - 1).max(0);
                     let mut total_weight = 0.0;
-                    for (size, _) in inner {
-                        total_size -= size.resolve(rect.dimension(), self.screen_size);
+                    for (size, child_node_id) in &inner {
+                        total_size -= resolve(size, *child_node_id);
                         if let Size::Weight(weight) = size {
                             total_weight += weight;
                         }
                     }
 
                     let mut pos = rect.y;
-                    for (size, child_node_id) in inner {
+                    for (size, child_node_id) in &inner {
                         let size = if let Size::Weight(weight) = size {
                             (total_size as f32 * (weight / total_weight)) as i32
                         } else {
-                            size.resolve(rect.dimension(), self.screen_size)
+                            resolve(size, *child_node_id)
                         }
                         .max(0);
-                        self.do_visit(scene, *child_node_id, Rect::new(rect.x, pos, rect.w, size));
-                        pos += size;
+                        let intrinsic = scene
+                            .node_aspect(*child_node_id)
+                            .map(|aspect| aspect.width_for(size));
+                        let (x, w) = align.place(rect.x, rect.w, intrinsic);
+                        self.do_visit(scene, *child_node_id, Rect::new(x, pos, w, size));
+                        pos += size + gap;
                     }
                 }
-                Node::Column { inner } => {
-                    let mut total_size = rect.w;
+                Node::Column { inner, gap, align } => {
+                    let inner = scene.visible_children(inner);
+                    // widths. Size::AspectOf is derived from the column height, and Size::FitContent from the child's content width.
+                    let screen_size = self.screen_size;
+                    let resolve = |size: &Size, child_node_id: NodeId| match size.aspect(scene) {
+                        Some(aspect) => aspect.width_for(rect.h),
+                        None if matches!(size, Size::FitContent) => scene
+                            .content_size(child_node_id, rect.dimension(), screen_size)
+                            .map_or(0, |content| content.width),
+                        None => size.resolve(rect.dimension(), screen_size, scene.scale_factor),
+                    };
+                    let gap = gap.resolve(rect.dimension(), screen_size, scene.scale_factor);
+                    let mut total_size = rect.w - gap * (inner.len() as i32 - 1).max(0);
                     let mut total_weight = 0.0;
-                    for (size, _) in inner {
-                        total_size -= size.resolve(rect.dimension(), self.screen_size);
+                    for (size, child_node_id) in &inner {
+                        total_size -= resolve(size, *child_node_id);
                         if let Size::Weight(weight) = size {
                             total_weight += weight;
                         }
                     }
 
                     let mut pos = rect.x;
-                    for (size, child_node_id) in inner {
+                    for (size, child_node_id) in &inner {
                         let size = if let Size::Weight(weight) = size {
                             (total_size as f32 * (weight / total_weight)) as i32
                         } else {
-                            size.resolve(rect.dimension(), self.screen_size)
+                            resolve(size, *child_node_id)
                         }
                         .max(0);
-                        self.do_visit(scene, *child_node_id, Rect::new(pos, rect.y, size, rect.h));
-                        pos += size;
+                        let intrinsic = scene
+                            .node_aspect(*child_node_id)
+                            .map(|aspect| aspect.height_for(size));
+                        let (y, h) = align.place(rect.y, rect.h, intrinsic);
+                        self.do_visit(scene, *child_node_id, Rect::new(pos, y, size, h));
+                        pos += size + gap;
                     }
                 }
-                Node::Rect { color } => self.rect_instances.push(renderer::FlatInstance {
-                    position: IVec3::new(rect.x, rect.y, self.z_index),
-                    scale: IVec2::new(rect.w, rect.h),
-                    color: *color,
-                }),
-                Node::Texture { texture_id } => {
-                    self.texture_instances.push(renderer::TextureInstance {
-                        position: IVec3::new(rect.x, rect.y, self.z_index),
-                        scale: IVec2::new(rect.w, rect.h),
+                Node::Scroll { child } => {
+                    let max_offset = (self.content_height(scene, *child, rect) - rect.h).max(0);
+                    let offset = scene.scroll_offset(node_id).min(max_offset);
+                    let parent_clip = self.clip;
+                    let clip = parent_clip.map_or(rect, |parent| parent.intersect(rect));
+                    self.scroll_ranges.push((node_id, clip, max_offset));
+                    self.clip = Some(clip);
+                    self.do_visit(
+                        scene,
+                        *child,
+                        Rect::new(rect.x, rect.y - offset, rect.w, rect.h),
+                    );
+                    self.clip = parent_clip;
+                }
+                Node::Padding { insets, child } => {
+                    let [left, top, right, bottom] = insets.each_ref().map(|inset| {
+                        inset.resolve(rect.dimension(), self.screen_size, scene.scale_factor)
+                    });
+                    let child_rect = Rect::new(
+                        rect.x + left,
+                        rect.y + top,
+                        (rect.w - left - right).max(0),
+                        (rect.h - top - bottom).max(0),
+                    );
+                    self.do_visit(scene, *child, child_rect);
+                }
+                Node::Opacity { alpha, child } => self.visit_group(scene, *alpha, *child, rect),
+                Node::Shadow {
+                    child,
+                    offset,
+                    blur,
+                    color,
+                } => {
+                    let screen_size = self.screen_size;
+                    let resolve = |size: &Size| {
+                        size.resolve(rect.dimension(), screen_size, scene.scale_factor)
+                    };
+                    let (x, y) = (resolve(&offset.0), resolve(&offset.1));
+                    let blur = resolve(blur).max(0);
+                    // pushed before the child, so that the child draws on top
+                    let depth_key = self.depth_key();
+                    self.push_primitive(draw::DrawCommand::Shadow {
+                        rect: Rect::new(rect.x + x, rect.y + y, rect.w, rect.h),
+                        depth: depth_key,
+                        color: *color,
+                        blur,
+                    });
+                    self.do_visit(scene, *child, rect);
+                }
+                Node::Rect { color } => self.push_rect(node_id, rect, *color, 0, None),
+                Node::RoundedRect { color, radius } => {
+                    let radius =
+                        radius.resolve(rect.dimension(), self.screen_size, scene.scale_factor);
+                    self.push_rect(node_id, rect, *color, radius, None);
+                }
+                Node::BorderedRect {
+                    fill,
+                    border,
+                    width,
+                } => {
+                    let width =
+                        width.resolve(rect.dimension(), self.screen_size, scene.scale_factor);
+                    self.push_rect(node_id, rect, *fill, 0, Some((width, *border)));
+                }
+                Node::Gradient { start, end, angle } => {
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    self.push_primitive(draw::DrawCommand::GradientRect {
+                        rect,
+                        depth: depth_key,
+                        start: *start,
+                        end: *end,
+                        angle: *angle,
+                    });
+                }
+                Node::Line {
+                    points,
+                    width,
+                    color,
+                } => {
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    let screen_size = self.screen_size;
+                    let resolve = |size: &Size| {
+                        size.resolve(rect.dimension(), screen_size, scene.scale_factor)
+                    };
+                    let mut points: Vec<Vec2> = points
+                        .iter()
+                        .map(|(x, y)| {
+                            Vec2::new((rect.x + resolve(x)) as f32, (rect.y + resolve(y)) as f32)
+                        })
+                        .collect();
+                    // zero-length segments have no direction
+                    points.dedup();
+                    let width = resolve(width);
+                    if points.len() >= 2 && width > 0 {
+                        let half = width as f32 / 2.0;
+                        let min =
+                            points.iter().copied().reduce(Vec2::min).unwrap_or_default() - half;
+                        let max =
+                            points.iter().copied().reduce(Vec2::max).unwrap_or_default() + half;
+                        self.push_primitive(draw::DrawCommand::Polyline {
+                            rect: Rect::new(
+                                min.x.floor() as i32,
+                                min.y.floor() as i32,
+                                (max.x.ceil() - min.x.floor()) as i32,
+                                (max.y.ceil() - min.y.floor()) as i32,
+                            ),
+                            depth: depth_key,
+                            points,
+                            width: width as f32,
+                            color: *color,
+                        });
+                    }
+                }
+                Node::Circle { color } => {
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    self.push_primitive(draw::DrawCommand::FilledEllipse {
+                        rect,
+                        depth: depth_key,
+                        color: *color,
+                    });
+                }
+                Node::Texture {
+                    texture_id,
+                    tint,
+                    uv_min,
+                    uv_max,
+                    flip_x,
+                    flip_y,
+                } => {
+                    // mirroring swaps the region's edges, so the quad samples it backwards
+                    let (mut uv_min, mut uv_max) = (*uv_min, *uv_max);
+                    if *flip_x {
+                        std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+                    }
+                    if *flip_y {
+                        std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+                    }
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    self.push_primitive(draw::DrawCommand::TexturedQuad {
+                        rect,
+                        depth: depth_key,
                         texture_id: *texture_id,
-                    })
+                        tint: *tint,
+                        uv_min,
+                        uv_max,
+                        translucent: false,
+                    });
+                }
+                Node::NinePatch { texture_id, insets } => {
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    let regions = match scene.texture_size(*texture_id) {
+                        Some(texture_size) => draw::nine_patch(
+                            rect,
+                            texture_size,
+                            insets.each_ref().map(|inset| {
+                                inset.resolve(
+                                    rect.dimension(),
+                                    self.screen_size,
+                                    scene.scale_factor,
+                                )
+                            }),
+                        ),
+                        None => vec![(rect, Vec2::ZERO, Vec2::ONE)],
+                    };
+                    // the regions do not overlap, so they share a depth
+                    for (region, uv_min, uv_max) in regions {
+                        self.push_primitive(draw::DrawCommand::TexturedQuad {
+                            rect: region,
+                            depth: depth_key,
+                            texture_id: *texture_id,
+                            tint: Vec4::ONE,
+                            uv_min,
+                            uv_max,
+                            translucent: false,
+                        });
+                    }
+                }
+                Node::Text { text, size, color } => {
+                    let depth_key = self.depth_key();
+                    self.add_drawn_node(node_id, rect, depth_key);
+                    if let Some(font) = &scene.font {
+                        let size =
+                            size.resolve(rect.dimension(), self.screen_size, scene.scale_factor);
+                        // the glyphs do not overlap, so they share a depth
+                        for (glyph, uv_min, uv_max) in
+                            font.layout(text, (rect.x, rect.y), size as f32)
+                        {
+                            self.push_primitive(draw::DrawCommand::TexturedQuad {
+                                rect: glyph,
+                                depth: depth_key,
+                                texture_id: font.texture_id,
+                                tint: *color,
+                                uv_min,
+                                uv_max,
+                                translucent: true,
+                            });
+                        }
+                    }
                 }
                 Node::Empty => (),
             }
         }
+
+        self.transform = parent_transform;
+        self.blend = parent_blend;
+        self.ancestors.remove(&node_id);
     }
 }
 
 struct Handler<'a> {
-    state: Option<State<'a>>,
-    guiug: Option<Guiug<'a>>,
-    title: &'a str,
+    // wakes the event loop when a SceneHandle sends an update
+    proxy: winit::event_loop::EventLoopProxy<()>,
+    states: HashMap<winit::window::WindowId, State<'a>>,
+    // windows to open when the event loop resumes, with their titles
+    pending: Vec<(String, Guiug<'a>)>,
+    // set up with the first window, and shared by the others
+    device: Option<gpu::SharedDevice>,
+    // setup failure, returned from run after the event loop exits
+    error: Option<GuiugError>,
 }
 
-impl<'a> winit::application::ApplicationHandler for Handler<'a> {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        let window = event_loop
-            .create_window(
-                winit::window::Window::default_attributes()
-                    .with_inner_size(winit::dpi::PhysicalSize::new(800, 800))
-                    .with_title(self.title)
-                    .with_visible(false),
-            )
-            .unwrap();
+impl<'a> Handler<'a> {
+    fn create_states(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) -> Result<(), GuiugError> {
+        while !self.pending.is_empty() {
+            let (title, mut guiug) = self.pending.remove(0);
+            self.pending.append(&mut guiug.windows);
+            self.create_state(event_loop, &title, guiug)?;
+        }
+        Ok(())
+    }
+
+    fn create_state(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        title: &str,
+        guiug: Guiug<'a>,
+    ) -> Result<(), GuiugError> {
+        if let Some((_, handle)) = &guiug.updates
+            && let Ok(mut waker) = handle.waker.lock()
+        {
+            *waker = Some(self.proxy.clone());
+        }
+        let window = event_loop.create_window(guiug.window_config.attributes(title))?;
         let window = Arc::new(window);
-        self.state = Some(pollster::block_on(State::new(
-            window.clone(),
-            self.guiug.take().unwrap(),
-        )));
+        let (shared, surface) = match self.device.take() {
+            Some(shared) => {
+                let surface = shared.instance.create_surface(window.clone())?;
+                (shared, surface)
+            }
+            None => pollster::block_on(gpu::SharedDevice::new(guiug.gpu_config, window.clone()))?,
+        };
+        let shared = self.device.insert(shared);
+        self.states.insert(
+            window.id(),
+            State::new(window.clone(), guiug, shared, surface)?,
+        );
 
         window.set_visible(true);
+        Ok(())
+    }
+}
+
+impl<'a> winit::application::ApplicationHandler for Handler<'a> {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Err(err) = self.create_states(event_loop) {
+            self.error = Some(err);
+            event_loop.exit();
+        }
+    }
+
+    // A SceneHandle sent an update, applied when the window draws.
+    fn user_event(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop, _event: ()) {
+        for state in self.states.values() {
+            if state.updates.is_some() {
+                state.window.request_redraw();
+            }
+        }
+    }
+
+    // Poll the shader files, and redraw the windows whose renderers were rebuilt.
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    fn new_events(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        cause: winit::event::StartCause,
+    ) {
+        if let winit::event::StartCause::ResumeTimeReached { .. } | winit::event::StartCause::Init =
+            cause
+        {
+            for state in self.states.values_mut() {
+                if state.gpu.reload_shaders() {
+                    state.window.request_redraw();
+                }
+            }
+            event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(
+                Instant::now() + hot_reload::POLL_INTERVAL,
+            ));
+        }
     }
 
     fn window_event(
         &mut self,
         event_loop: &winit::event_loop::ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
-        let state = match &mut self.state {
-            Some(state) => state,
-            None => return,
+        let Some(state) = self.states.get_mut(&window_id) else {
+            return;
         };
 
         match event {
-            winit::event::WindowEvent::CloseRequested => event_loop.exit(),
+            winit::event::WindowEvent::CloseRequested => {
+                self.states.remove(&window_id);
+                if self.states.is_empty() {
+                    event_loop.exit();
+                }
+            }
             winit::event::WindowEvent::RedrawRequested => {
                 state.update();
-                if let Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) =
-                    state.render()
-                {
-                    let size = state.window.inner_size();
-                    state.resize(size.width, size.height);
+                match state.render() {
+                    Ok(()) => (),
+                    // reconfigure the surface, and draw the next frame on it
+                    Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+                        let size = state.window.inner_size();
+                        state.resize(size.width, size.height);
+                    }
+                    // skip the frame, and try again
+                    Err(wgpu::SurfaceError::Timeout) => state.window.request_redraw(),
+                    // retrying would fail the same way every frame
+                    Err(wgpu::SurfaceError::OutOfMemory) => {
+                        eprintln!("guiug: out of memory for the next frame, exiting");
+                        event_loop.exit();
+                        return;
+                    }
+                    Err(err @ wgpu::SurfaceError::Other) => {
+                        eprintln!("guiug: skipped a frame: {err}");
+                    }
                 }
+                if state.scene.take_redraw() {
+                    state.window.request_redraw();
+                }
+            }
+            // the window is resized to the new physical size after this
+            winit::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                state.scene.set_scale_factor(scale_factor as f32);
             }
             winit::event::WindowEvent::Resized(winit::dpi::PhysicalSize { width, height }) => {
                 state.resize(width, height);
             }
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                state.cursor_position = (position.x as i32, position.y as i32);
+                state.handle_event(UiEvent::CursorMoved {
+                    position: state.cursor_position,
+                });
+            }
+            winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                let delta = event::wheel_delta(delta);
+                let screen_size = Dimension::new(
+                    state.surface_configuration.width as i32,
+                    state.surface_configuration.height as i32,
+                );
+                // positive y scrolls up, toward the top of the content
+                if state
+                    .scene
+                    .scroll_at(state.cursor_position, -delta.1.round() as i32, screen_size)
+                    .is_some()
+                {
+                    state.window.request_redraw();
+                }
+                state.handle_event(UiEvent::MouseWheel {
+                    delta,
+                    position: state.cursor_position,
+                });
+            }
+            winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                state.modifiers = modifiers.state().into();
+            }
+            winit::event::WindowEvent::KeyboardInput { event, .. } => {
+                state.handle_event(UiEvent::KeyboardInput {
+                    key: event.logical_key,
+                    pressed: event.state.is_pressed(),
+                    modifiers: state.modifiers,
+                });
+            }
+            winit::event::WindowEvent::MouseInput {
+                state: element_state,
+                button,
+                ..
+            } => {
+                state.handle_event(UiEvent::MouseInput {
+                    button: button.into(),
+                    pressed: element_state.is_pressed(),
+                    position: state.cursor_position,
+                });
+            }
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coplanar_siblings_get_distinct_depth_in_declaration_order() {
+        let mut guiug = Guiug::default();
+        let inner = (0..16)
+            .map(|index| {
+                let rect = guiug.rect_node(Vec4::new(index as f32 / 16.0, 0.0, 0.0, 1.0));
+                let position = Position::fill_with_margin(Size::Pixel(index)).with_z_index(3);
+                (position, rect)
+            })
+            .collect();
+        let root = guiug.layer_node(inner);
+        guiug.set_root(root).unwrap();
+
+        let depths = |scene: &Scene| -> Vec<i32> {
+            NodeVisitor::visit(Dimension::new(100, 100), scene)
+                .primitives
+                .iter()
+                .filter_map(|(_, _, _, command)| command.depth())
+                .collect()
+        };
+        let first = depths(guiug.scene_mut());
+        // a later declared sibling is nearer, one rank in front of the previous one
+        assert_eq!(first, (0..16).rev().collect::<Vec<_>>());
+        assert_eq!(depths(guiug.scene_mut()), first);
+    }
+
+    #[test]
+    fn flipped_sprite_samples_its_region_backwards() {
+        let (min, max) = (Vec2::new(0.25, 0.5), Vec2::new(0.5, 0.75));
+        let cases = [
+            (false, false, min, max),
+            (true, false, Vec2::new(0.5, 0.5), Vec2::new(0.25, 0.75)),
+            (false, true, Vec2::new(0.25, 0.75), Vec2::new(0.5, 0.5)),
+            (true, true, max, min),
+        ];
+        for (flip_x, flip_y, uv_min, uv_max) in cases {
+            let mut guiug = Guiug::default();
+            let sprite = guiug.sprite_node(0, min, max);
+            guiug.set_root(sprite).unwrap();
+            guiug
+                .scene_mut()
+                .set_texture_flip(sprite, flip_x, flip_y)
+                .unwrap();
+
+            let draw_list = draw::lower(guiug.scene_mut(), Dimension::new(64, 64));
+            let [
+                draw::DrawCommand::TexturedQuad {
+                    uv_min: quad_min,
+                    uv_max: quad_max,
+                    ..
+                },
+            ] = draw_list.commands.as_slice()
+            else {
+                panic!("expected one textured quad, got {:?}", draw_list.commands);
+            };
+            assert_eq!(
+                (*quad_min, *quad_max),
+                (uv_min, uv_max),
+                "flip {flip_x} {flip_y}"
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use guiug::{Anchor, Guiug, Position, Size, Vec4};
+use guiug::{Anchor, CrossAlign, Guiug, Position, Size, Vec4};
 
 fn main() {
     let mut guiug = Guiug::default();
@@ -11,7 +11,7 @@ fn main() {
     let gamma_texture = guiug.add_texture(include_bytes!("res/gamma-ramp32.png"));
 
     // construct scene
-    let mut root = vec![
+    let textures = vec![
         (
             Position::new(
                 Anchor::start(Size::ParentWidth(0.2), Size::ScreenWidth(0.2)),
@@ -56,19 +56,23 @@ fn main() {
         for j in 0..10 {
             let color = Vec4::new(0.1 * i as f32, 0.1 * j as f32, 0.0, 1.0);
             let rect_node = guiug.rect_node(color);
-            // layer node for margin
-            let layer_node = guiug.layer_node(vec![(
-                Position::new(
-                    Anchor::center(Size::ZERO, Size::ParentWidth(0.8)),
-                    Anchor::center(Size::ZERO, Size::ParentHeight(0.8)),
-                ),
-                rect_node,
-            )]);
-            col_vec.push((Size::Weight(1.0), layer_node));
+            let margin = [
+                Size::ParentWidth(0.1),
+                Size::ParentHeight(0.1),
+                Size::ParentWidth(0.1),
+                Size::ParentHeight(0.1),
+            ];
+            col_vec.push((Size::Weight(1.0), guiug.padding_node(margin, rect_node)));
         }
-        row_vec.push((Size::Weight(1.0), guiug.column_node(col_vec)));
+        row_vec.push((
+            Size::Weight(1.0),
+            guiug.column_node(col_vec, Size::ZERO, CrossAlign::Stretch),
+        ));
     }
-    root.push((Position::FULL, guiug.row_node(row_vec)));
+    let mut root = vec![(
+        Position::FULL,
+        guiug.row_node(row_vec, Size::ZERO, CrossAlign::Stretch),
+    )];
 
     // Row & Column demonstration
     let col_vec = vec![
@@ -96,7 +100,10 @@ fn main() {
             Size::Pixel(100),
             guiug.rect_node(Vec4::new(1.0, 0.0, 0.0, 1.0)),
         ),
-        (Size::Weight(1.0), guiug.column_node(col_vec.clone())),
+        (
+            Size::Weight(1.0),
+            guiug.column_node(col_vec.clone(), Size::ZERO, CrossAlign::Stretch),
+        ),
         (
             Size::Weight(1.0),
             guiug.rect_node(Vec4::new(0.0, 0.0, 1.0, 1.0)),
@@ -108,11 +115,16 @@ fn main() {
         ),
     ];
 
-    root.push((Position::FULL, guiug.row_node(row_vec)));
+    root.push((
+        Position::FULL,
+        guiug.row_node(row_vec, Size::ZERO, CrossAlign::Stretch),
+    ));
 
+    // declared last, so that they draw on top of the tiles
+    root.extend(textures);
     let root_node = guiug.layer_node(root);
-    guiug.set_root(root_node);
+    guiug.set_root(root_node).unwrap();
 
     // run scene
-    guiug::run("wonderful program", guiug);
+    guiug::run("wonderful program", guiug).unwrap();
 }
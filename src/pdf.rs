@@ -0,0 +1,492 @@
+//! PDF export of the laid out scene, as a single page with one point per pixel.
+//! Shader effects such as blur are not reproduced; textures are embedded as their decoded pixels.
+//! Shadows are stacked translucent rects, and opacity groups fade each of their nodes instead of the group as a whole.
+
+use std::collections::HashMap;
+
+use pdf_writer::{
+    Content, Filter, Finish, Name, Pdf, Ref,
+    types::{FunctionShadingType, LineJoinStyle},
+};
+
+use crate::{
+    draw::{self, DrawCommand},
+    scene::Scene,
+    texture::{self, TextureId, TextureInfo, TextureInfoManager},
+    types::{Dimension, Rect},
+};
+
+const CATALOG_ID: Ref = Ref::new(1);
+const PAGE_TREE_ID: Ref = Ref::new(2);
+const PAGE_ID: Ref = Ref::new(3);
+const CONTENT_ID: Ref = Ref::new(4);
+
+pub(crate) fn render(
+    scene: &Scene,
+    texture_infos: Option<&TextureInfoManager>,
+    screen_size: Dimension,
+) -> Vec<u8> {
+    let commands = draw::lower(scene, screen_size).flatten();
+    let mut writer = Writer {
+        pdf: Pdf::new(),
+        next_id: Ref::new(5),
+        graphics_states: Vec::new(),
+        shadings: Vec::new(),
+        alphas: HashMap::new(),
+        images: HashMap::new(),
+    };
+    writer.pdf.catalog(CATALOG_ID).pages(PAGE_TREE_ID);
+    writer.pdf.pages(PAGE_TREE_ID).kids([PAGE_ID]).count(1);
+
+    // PDF puts the origin at the bottom left, the scene at the top left.
+    let height = screen_size.height as f32;
+    let flip = |rect: &Rect| {
+        (
+            rect.x as f32,
+            height - (rect.y + rect.h) as f32,
+            rect.w.max(0) as f32,
+            rect.h.max(0) as f32,
+        )
+    };
+
+    let mut content = Content::new();
+
+    let background = scene.background();
+    if background.w > 0.0 {
+        content.save_state();
+        if background.w < 1.0 {
+            let name = writer.alpha_state(background.w);
+            content.set_parameters(Name(name.as_bytes()));
+        }
+        fill_color(&mut content, background);
+        content.rect(0.0, 0.0, screen_size.width as f32, height);
+        content.fill_nonzero();
+        content.restore_state();
+    }
+
+    // transform of rotated nodes in PDF space, applied to each primitive so that restoring the clip keeps it
+    let mut transform = None;
+    let mut clipped = false;
+    for command in &commands {
+        match command {
+            DrawCommand::Transform(affine) => {
+                // conjugate the layout transform with the flip to the bottom-left origin
+                transform = affine.map(|affine| {
+                    let [a, b, c, d] = affine.matrix2.to_cols_array();
+                    let [e, f] = affine.translation.to_array();
+                    [a, -b, -c, d, c * height + e, height - d * height - f]
+                });
+            }
+            // blend modes are not reproduced, and groups are flattened
+            DrawCommand::Blend(_) | DrawCommand::Group { .. } => (),
+            DrawCommand::Clip(clip) => {
+                // a clip can only be lifted by restoring the state saved before it
+                if clipped {
+                    content.restore_state();
+                    clipped = false;
+                }
+                if let Some(clip) = clip {
+                    let (x, y, w, h) = flip(clip);
+                    content.save_state();
+                    content.rect(x, y, w, h);
+                    content.clip_nonzero();
+                    content.end_path();
+                    clipped = true;
+                }
+            }
+            DrawCommand::FilledRect {
+                rect,
+                color,
+                radius,
+                border,
+                ..
+            } => {
+                let (fill_rect, fill_radius) = match border {
+                    Some(border) => border.inner(*rect, *radius),
+                    None => (*rect, *radius),
+                };
+                let (x, y, w, h) = flip(&fill_rect);
+                let fill_radius = fill_radius as f32;
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                if color.w < 1.0 {
+                    let name = writer.alpha_state(color.w);
+                    content.set_parameters(Name(name.as_bytes()));
+                }
+                fill_color(&mut content, *color);
+                rounded_rect(&mut content, x, y, w, h, fill_radius, fill_radius);
+                content.fill_nonzero();
+                content.restore_state();
+
+                // the border is the ring between the outer and the inner outline
+                if let Some(border) = border {
+                    content.save_state();
+                    if let Some(matrix) = transform {
+                        content.transform(matrix);
+                    }
+                    if border.color.w < 1.0 {
+                        let name = writer.alpha_state(border.color.w);
+                        content.set_parameters(Name(name.as_bytes()));
+                    }
+                    fill_color(&mut content, border.color);
+                    let (outer_x, outer_y, outer_w, outer_h) = flip(rect);
+                    let radius = *radius as f32;
+                    rounded_rect(
+                        &mut content,
+                        outer_x,
+                        outer_y,
+                        outer_w,
+                        outer_h,
+                        radius,
+                        radius,
+                    );
+                    rounded_rect(&mut content, x, y, w, h, fill_radius, fill_radius);
+                    content.fill_even_odd();
+                    content.restore_state();
+                }
+            }
+            DrawCommand::GradientRect {
+                rect,
+                start,
+                end,
+                angle,
+                ..
+            } => {
+                let (x, y, w, h) = flip(rect);
+                let name = writer.gradient(*start, *end, *angle);
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                // PDF shadings have no per-stop alpha, so the average is used for the whole rect
+                let alpha = (start.w + end.w) / 2.0;
+                if alpha < 1.0 {
+                    let name = writer.alpha_state(alpha);
+                    content.set_parameters(Name(name.as_bytes()));
+                }
+                content.rect(x, y, w, h);
+                content.clip_nonzero();
+                content.end_path();
+                // the shading is laid out in the unit square with y pointing down, like the renderer does
+                content.transform([w, 0.0, 0.0, -h, x, y + h]);
+                content.shading(Name(name.as_bytes()));
+                content.restore_state();
+            }
+            DrawCommand::Polyline {
+                points,
+                width,
+                color,
+                ..
+            } => {
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                if color.w < 1.0 {
+                    let name = writer.alpha_state(color.w);
+                    content.set_parameters(Name(name.as_bytes()));
+                }
+                content.set_stroke_rgb(
+                    draw::srgb_encode(color.x),
+                    draw::srgb_encode(color.y),
+                    draw::srgb_encode(color.z),
+                );
+                content.set_line_width(*width);
+                content.set_line_join(LineJoinStyle::BevelJoin);
+                content.move_to(points[0].x, height - points[0].y);
+                for point in &points[1..] {
+                    content.line_to(point.x, height - point.y);
+                }
+                content.stroke();
+                content.restore_state();
+            }
+            DrawCommand::Checkerboard {
+                rect, color, cell, ..
+            } => {
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                if color.w < 1.0 {
+                    let name = writer.alpha_state(color.w);
+                    content.set_parameters(Name(name.as_bytes()));
+                }
+                fill_color(&mut content, *color);
+                for cell in draw::checkerboard_cells(*rect, *cell) {
+                    let (x, y, w, h) = flip(&cell);
+                    content.rect(x, y, w, h);
+                }
+                content.fill_nonzero();
+                content.restore_state();
+            }
+            DrawCommand::Shadow {
+                rect, color, blur, ..
+            } => {
+                for (layer, color) in draw::shadow_layers(*rect, *blur, *color) {
+                    let (x, y, w, h) = flip(&layer);
+                    content.save_state();
+                    if let Some(matrix) = transform {
+                        content.transform(matrix);
+                    }
+                    let name = writer.alpha_state(color.w);
+                    content.set_parameters(Name(name.as_bytes()));
+                    fill_color(&mut content, color);
+                    content.rect(x, y, w, h);
+                    content.fill_nonzero();
+                    content.restore_state();
+                }
+            }
+            DrawCommand::FilledEllipse { rect, color, .. } => {
+                let (x, y, w, h) = flip(rect);
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                if color.w < 1.0 {
+                    let name = writer.alpha_state(color.w);
+                    content.set_parameters(Name(name.as_bytes()));
+                }
+                fill_color(&mut content, *color);
+                // corners as large as the rect leave no straight edges
+                rounded_rect(&mut content, x, y, w, h, w / 2.0, h / 2.0);
+                content.fill_nonzero();
+                content.restore_state();
+            }
+            DrawCommand::TexturedQuad {
+                rect,
+                texture_id,
+                tint,
+                uv_min,
+                uv_max,
+                ..
+            } => {
+                let Some(texture_info) = texture::lookup(scene, texture_infos, *texture_id) else {
+                    continue;
+                };
+                // undecodable textures are skipped, as the renderer would fail to load them anyway
+                let Some(name) = writer.image(*texture_id, texture_info, *tint, (*uv_min, *uv_max))
+                else {
+                    continue;
+                };
+                let (x, y, w, h) = flip(rect);
+                content.save_state();
+                if let Some(matrix) = transform {
+                    content.transform(matrix);
+                }
+                content.transform([w, 0.0, 0.0, h, x, y]);
+                content.x_object(Name(name.as_bytes()));
+                content.restore_state();
+            }
+        }
+    }
+    if clipped {
+        content.restore_state();
+    }
+
+    let Writer {
+        mut pdf,
+        graphics_states,
+        shadings,
+        images,
+        ..
+    } = writer;
+
+    let mut page = pdf.page(PAGE_ID);
+    page.parent(PAGE_TREE_ID)
+        .media_box(pdf_writer::Rect::new(
+            0.0,
+            0.0,
+            screen_size.width as f32,
+            height,
+        ))
+        .contents(CONTENT_ID);
+    let mut resources = page.resources();
+    let mut states = resources.ext_g_states();
+    for (name, id) in &graphics_states {
+        states.pair(Name(name.as_bytes()), *id);
+    }
+    states.finish();
+    let mut shading_names = resources.shadings();
+    for (name, id) in &shadings {
+        shading_names.pair(Name(name.as_bytes()), *id);
+    }
+    shading_names.finish();
+    let mut x_objects = resources.x_objects();
+    for (name, id) in images.values().flatten() {
+        x_objects.pair(Name(name.as_bytes()), *id);
+    }
+    x_objects.finish();
+    resources.finish();
+    page.finish();
+
+    pdf.stream(CONTENT_ID, &content.finish());
+    pdf.finish()
+}
+
+/// Texture id, and bits of the tint and of the UV region applied to it.
+type ImageKey = (TextureId, [u32; 4], [[u32; 2]; 2]);
+
+struct Writer {
+    pdf: Pdf,
+    next_id: Ref,
+    /// Resource name and object of each graphics state, for the page resource dictionary.
+    graphics_states: Vec<(String, Ref)>,
+    /// Resource name and object of each gradient shading.
+    shadings: Vec<(String, Ref)>,
+    /// Graphics state name by fill alpha bits.
+    alphas: HashMap<u32, String>,
+    /// Resource name and object of each written texture by id and tint bits, `None` if it could not be decoded.
+    images: HashMap<ImageKey, Option<(String, Ref)>>,
+}
+
+impl Writer {
+    /// Name of a graphics state that sets the fill and stroke alpha, written on first use.
+    fn alpha_state(&mut self, alpha: f32) -> String {
+        let alpha = alpha.clamp(0.0, 1.0);
+        if let Some(name) = self.alphas.get(&alpha.to_bits()) {
+            return name.clone();
+        }
+        let name = format!("Gs{}", self.graphics_states.len());
+        let id = self.next_id.bump();
+        self.pdf
+            .ext_graphics(id)
+            .non_stroking_alpha(alpha)
+            .stroking_alpha(alpha);
+        self.graphics_states.push((name.clone(), id));
+        self.alphas.insert(alpha.to_bits(), name.clone());
+        name
+    }
+
+    /// Name of an axial shading from `start` to `end` color in the unit square, y pointing down.
+    /// Colors are interpolated between their sRGB-encoded values.
+    fn gradient(&mut self, start: glam::Vec4, end: glam::Vec4, angle: f32) -> String {
+        let encode = |color: glam::Vec4| {
+            [
+                draw::srgb_encode(color.x),
+                draw::srgb_encode(color.y),
+                draw::srgb_encode(color.z),
+            ]
+        };
+        let function_id = self.next_id.bump();
+        self.pdf
+            .exponential_function(function_id)
+            .domain([0.0, 1.0])
+            .c0(encode(start))
+            .c1(encode(end))
+            .n(1.0);
+
+        let vector = draw::gradient_vector(angle);
+        let shading_id = self.next_id.bump();
+        let mut shading = self.pdf.function_shading(shading_id);
+        shading.shading_type(FunctionShadingType::Axial);
+        shading.color_space().device_rgb();
+        shading.function(function_id);
+        shading.coords([
+            0.5 - vector.x / 2.0,
+            0.5 - vector.y / 2.0,
+            0.5 + vector.x / 2.0,
+            0.5 + vector.y / 2.0,
+        ]);
+        shading.extend([true, true]);
+        shading.finish();
+
+        let name = format!("Sh{}", self.shadings.len());
+        self.shadings.push((name.clone(), shading_id));
+        name
+    }
+
+    /// Name of the image XObject of a texture region with the tint applied, written on first use.
+    fn image(
+        &mut self,
+        texture_id: TextureId,
+        texture_info: &TextureInfo,
+        tint: glam::Vec4,
+        (uv_min, uv_max): (glam::Vec2, glam::Vec2),
+    ) -> Option<String> {
+        let key = (
+            texture_id,
+            tint.to_array().map(f32::to_bits),
+            [uv_min, uv_max].map(|uv| uv.to_array().map(f32::to_bits)),
+        );
+        if let Some(image) = self.images.get(&key) {
+            return image.as_ref().map(|(name, _)| name.clone());
+        }
+        let name = format!("Im{}", self.images.len());
+        let image = texture_info.to_rgba8().ok().map(|image| {
+            let mut image = draw::crop_uv(image, uv_min, uv_max);
+            draw::tint_rgba8(&mut image, tint);
+            (name, write_image(&mut self.pdf, &mut self.next_id, &image))
+        });
+        self.images.insert(key, image.clone());
+        image.map(|(name, _)| name)
+    }
+}
+
+// Colors are linear, and the sRGB surface encodes them on write. PDF device colors are sRGB-encoded.
+fn fill_color(content: &mut Content, color: glam::Vec4) {
+    content.set_fill_rgb(
+        draw::srgb_encode(color.x),
+        draw::srgb_encode(color.y),
+        draw::srgb_encode(color.z),
+    );
+}
+
+/// Add a rect path whose corners are quarter ellipses with radii `rx` and `ry`, approximated with cubic curves.
+fn rounded_rect(content: &mut Content, x: f32, y: f32, w: f32, h: f32, rx: f32, ry: f32) {
+    if rx <= 0.0 || ry <= 0.0 {
+        content.rect(x, y, w, h);
+        return;
+    }
+    // distance of the control points from the corner ends, for a quarter circle of radius 1
+    const KAPPA: f32 = 0.552_284_8;
+    let (kx, ky) = (rx * (1.0 - KAPPA), ry * (1.0 - KAPPA));
+    let (right, top) = (x + w, y + h);
+    content.move_to(x + rx, y);
+    content.line_to(right - rx, y);
+    content.cubic_to(right - kx, y, right, y + ky, right, y + ry);
+    content.line_to(right, top - ry);
+    content.cubic_to(right, top - ky, right - kx, top, right - rx, top);
+    content.line_to(x + rx, top);
+    content.cubic_to(x + kx, top, x, top - ky, x, top - ry);
+    content.line_to(x, y + ry);
+    content.cubic_to(x, y + ky, x + kx, y, x + rx, y);
+    content.close_path();
+}
+
+/// Write an image as a compressed RGB image with a soft mask for its alpha.
+fn write_image(pdf: &mut Pdf, next_id: &mut Ref, image: &image::RgbaImage) -> Ref {
+    let (width, height) = image.dimensions();
+
+    let mut color = Vec::with_capacity((width * height * 3) as usize);
+    let mut alpha = Vec::with_capacity((width * height) as usize);
+    for pixel in image.pixels() {
+        color.extend_from_slice(&pixel.0[..3]);
+        alpha.push(pixel.0[3]);
+    }
+    let color = miniz_oxide::deflate::compress_to_vec_zlib(&color, 6);
+    let alpha = miniz_oxide::deflate::compress_to_vec_zlib(&alpha, 6);
+
+    let image_id = next_id.bump();
+    let mask_id = next_id.bump();
+
+    let mut image = pdf.image_xobject(image_id, &color);
+    image.filter(Filter::FlateDecode);
+    image.width(width as i32);
+    image.height(height as i32);
+    image.color_space().device_rgb();
+    image.bits_per_component(8);
+    image.s_mask(mask_id);
+    image.finish();
+
+    let mut mask = pdf.image_xobject(mask_id, &alpha);
+    mask.filter(Filter::FlateDecode);
+    mask.width(width as i32);
+    mask.height(height as i32);
+    mask.color_space().device_gray();
+    mask.bits_per_component(8);
+    mask.finish();
+
+    image_id
+}
@@ -1,88 +1,1439 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use crate::{
-    texture,
-    types::{Dimension, Rect},
+    GuiugError, NodeVisitor,
+    animation::{Animation, AnimationId, AnimationKind, AnimationUpdate, Animator, Easing, Path},
+    color::ColorSpace,
+    draw, text,
+    texture::{self, TextureId},
+    types::{BoundingBox, Dimension, Rect},
 };
-use glam::Vec4;
+use glam::{Vec2, Vec4};
 
 pub type NodeId = u32;
 
-/// You have to call `set_root` the root node
-#[derive(Default, Debug)]
+/// Default value of [Scene::set_max_depth].
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Node tree of a guiug application, with the state of its animations and scene-owned textures.
+/// Usually built through [crate::Guiug]. You have to call `set_root` the root node
+#[derive(Debug)]
 pub struct Scene {
     last_id: NodeId,
     pub(crate) nodes: HashMap<NodeId, Node>,
     pub(crate) root_node: Option<NodeId>,
+    animator: Animator,
+    pub(crate) frame_stats: FrameStats,
+    pub(crate) max_depth: usize,
+    pub(crate) continuous: bool,
+    pub(crate) redraw_requested: bool,
+    background: Vec4,
+    pub(crate) color_space: ColorSpace,
+    pub(crate) texture_errors: Vec<(texture::TextureId, texture::TextureError)>,
+    last_texture_id: texture::TextureId,
+    // textures registered or updated through the scene, which owns their data
+    pub(crate) texture_infos: texture::TextureInfoManager<'static>,
+    // textures registered or updated through the scene that are not uploaded yet
+    pub(crate) pending_textures: Vec<texture::TextureId>,
+    // sizes of textures, read from their headers when registered with the builder and updated when uploaded
+    pub(crate) texture_sizes: HashMap<texture::TextureId, (u32, u32)>,
+    pub(crate) fallback_adapter: bool,
+    pub(crate) sample_count: u32,
+    // rotation in radians of rotated nodes
+    pub(crate) rotations: HashMap<NodeId, f32>,
+    // blend mode of nodes drawn with another mode than BlendMode::Replace
+    pub(crate) blend_modes: HashMap<NodeId, BlendMode>,
+    // nodes hidden with their subtrees
+    pub(crate) hidden: HashSet<NodeId>,
+    // whether anything drawn changed since the instance buffers were last built
+    pub(crate) dirty: bool,
+    pub(crate) debug_bounds: bool,
+    pub(crate) scale_factor: f32,
+    // drawable size of the window showing the scene
+    pub(crate) screen_size: Dimension,
+    // scroll offset in pixels of scrolled Scroll nodes
+    pub(crate) scroll_offsets: HashMap<NodeId, i32>,
+    // font of the Text nodes and its glyph atlas
+    pub(crate) font: Option<text::Font>,
+    // where to save the next frame
+    pub(crate) screenshot_request: Option<PathBuf>,
+    pub(crate) screenshot_errors: Vec<(PathBuf, GuiugError)>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            last_id: 0,
+            nodes: HashMap::new(),
+            root_node: None,
+            animator: Animator::default(),
+            frame_stats: FrameStats::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            continuous: false,
+            redraw_requested: false,
+            background: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            color_space: ColorSpace::Linear,
+            texture_errors: Vec::new(),
+            screenshot_request: None,
+            screenshot_errors: Vec::new(),
+            last_texture_id: 0,
+            texture_infos: texture::TextureInfoManager::default(),
+            pending_textures: Vec::new(),
+            texture_sizes: HashMap::new(),
+            fallback_adapter: false,
+            sample_count: 1,
+            rotations: HashMap::new(),
+            blend_modes: HashMap::new(),
+            hidden: HashSet::new(),
+            dirty: true,
+            debug_bounds: false,
+            scale_factor: 1.0,
+            screen_size: Dimension::new(0, 0),
+            scroll_offsets: HashMap::new(),
+            font: None,
+        }
+    }
+}
+
+// Serialized part of the scene: the node tree and how it is drawn. Animations, handlers and frame state are left out,
+// and textures are referenced by id only.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SceneData<'a> {
+    last_id: NodeId,
+    root_node: Option<NodeId>,
+    nodes: std::borrow::Cow<'a, HashMap<NodeId, Node>>,
+    max_depth: usize,
+    background: Vec4,
+    rotations: std::borrow::Cow<'a, HashMap<NodeId, f32>>,
+    // missing in scenes saved before blend modes existed
+    #[serde(default)]
+    blend_modes: std::borrow::Cow<'a, HashMap<NodeId, BlendMode>>,
+    #[serde(default)]
+    hidden: std::borrow::Cow<'a, HashSet<NodeId>>,
+    #[serde(default)]
+    color_space: ColorSpace,
+}
+
+/// Serializes the nodes with their ids, the root node, the background and color space, and node rotations, blend
+/// modes and visibility.
+/// Animations and textures are not serialized: texture ids are kept as plain integers, so the caller has to
+/// register the same textures in the same order after loading for the ids to match.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Scene {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SceneData {
+            last_id: self.last_id,
+            root_node: self.root_node,
+            nodes: std::borrow::Cow::Borrowed(&self.nodes),
+            max_depth: self.max_depth,
+            background: self.background,
+            rotations: std::borrow::Cow::Borrowed(&self.rotations),
+            blend_modes: std::borrow::Cow::Borrowed(&self.blend_modes),
+            hidden: std::borrow::Cow::Borrowed(&self.hidden),
+            color_space: self.color_space,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Loads a scene saved with its [serde::Serialize] impl. Put it in the builder with `*guiug.scene_mut() = scene`
+/// before adding any texture, so that textures added in the original order get their original ids.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Scene {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = SceneData::deserialize(deserializer)?;
+        Ok(Self {
+            last_id: data.last_id,
+            root_node: data.root_node,
+            nodes: data.nodes.into_owned(),
+            max_depth: data.max_depth,
+            background: data.background,
+            rotations: data.rotations.into_owned(),
+            blend_modes: data.blend_modes.into_owned(),
+            hidden: data.hidden.into_owned(),
+            color_space: data.color_space,
+            ..Self::default()
+        })
+    }
+}
+
+/// Problem found while traversing the scene tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SceneError {
+    /// The node contains itself as a descendant.
+    Cycle { node_id: NodeId },
+
+    /// The node is nested deeper than the max depth.
+    TooDeep { node_id: NodeId, max_depth: usize },
+
+    /// Inserting `child` into `parent` would make `parent` its own descendant.
+    WouldCycle { parent: NodeId, child: NodeId },
+
+    /// The node does not exist, or is not a Layer node where a Layer node is required.
+    NotLayer { node_id: NodeId },
+
+    /// The node does not exist, or is not a Texture node where a Texture node is required.
+    NotTexture { node_id: NodeId },
+
+    /// The node does not exist, or is not an opacity group node where one is required.
+    NotOpacityGroup { node_id: NodeId },
+
+    /// The node is not placed in a Layer node, so it has no [Position].
+    NotInLayer { node_id: NodeId },
+
+    /// The node's position and the target position have anchors or sizes of different kinds, which cannot be tweened.
+    PositionMismatch { node_id: NodeId },
+
+    /// The node does not exist, or has no single color, like container, Gradient and NinePatch nodes.
+    NoColor { node_id: NodeId },
+
+    /// No node has the id, e.g. a typo'd or stale id.
+    NotFound { node_id: NodeId },
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Cycle { node_id } => {
+                write!(f, "node {node_id} contains itself as a descendant")
+            }
+            SceneError::TooDeep { node_id, max_depth } => {
+                write!(f, "node {node_id} is nested deeper than {max_depth}")
+            }
+            SceneError::WouldCycle { parent, child } => {
+                write!(
+                    f,
+                    "inserting node {child} into node {parent} would create a cycle"
+                )
+            }
+            SceneError::NotLayer { node_id } => write!(f, "node {node_id} is not a layer node"),
+            SceneError::NotTexture { node_id } => {
+                write!(f, "node {node_id} is not a texture node")
+            }
+            SceneError::NotOpacityGroup { node_id } => {
+                write!(f, "node {node_id} is not an opacity group node")
+            }
+            SceneError::NotInLayer { node_id } => {
+                write!(f, "node {node_id} is not placed in a layer node")
+            }
+            SceneError::PositionMismatch { node_id } => write!(
+                f,
+                "the position of node {node_id} cannot be tweened to a position of different anchor or size kinds"
+            ),
+            SceneError::NoColor { node_id } => write!(f, "node {node_id} has no color"),
+            SceneError::NotFound { node_id } => write!(f, "node {node_id} does not exist"),
+        }
+    }
 }
 
+impl std::error::Error for SceneError {}
+
 impl Scene {
+    /// Create empty scene with room for at least `capacity` nodes without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: HashMap::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Limit how deep the scene tree is traversed. Subtrees nested deeper than `max_depth` are not laid out nor drawn.
+    /// Default is [DEFAULT_MAX_DEPTH].
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+        self.dirty = true;
+    }
+
+    /// Set color the screen is cleared with before drawing nodes. Default is opaque black.
+    pub fn set_background(&mut self, color: Vec4) {
+        self.background = color;
+        self.dirty = true;
+        self.redraw_requested = true;
+    }
+
+    /// Background color in linear space, whatever the color space it was given in.
+    pub(crate) fn background(&self) -> Vec4 {
+        match self.color_space {
+            ColorSpace::Linear => self.background,
+            ColorSpace::Srgb => Vec4::new(
+                draw::srgb_decode(self.background.x),
+                draw::srgb_decode(self.background.y),
+                draw::srgb_decode(self.background.z),
+                self.background.w,
+            ),
+        }
+    }
+
+    /// Set how node colors, tints and the background are interpreted, e.g. [ColorSpace::Srgb] to give colors as
+    /// sRGB-encoded values where `0.5` is mid-gray. Default is [ColorSpace::Linear].
+    /// Colors are decoded for drawing only: [Self::animate_color] interpolates them as given, which is perceptually
+    /// even in sRGB.
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+        self.dirty = true;
+        self.redraw_requested = true;
+    }
+
+    /// Outline the rect of every node laid out, above all nodes and ignoring clipping layers, to debug layout.
+    /// Colors by node kind: Layer, Scroll and Padding red, Row green, Column blue, Texture and NinePatch magenta, Empty gray,
+    /// other display nodes yellow. Empty nodes are also filled with a faint checkerboard, to show the space they take.
+    /// Default is off.
+    pub fn set_debug_bounds(&mut self, enabled: bool) {
+        self.debug_bounds = enabled;
+        self.dirty = true;
+        self.redraw_requested = true;
+    }
+
+    /// Set the number of physical pixels per logical pixel that [Size::LogicalPixel] resolves with. Default is 1.
+    /// The window sets it to the display's scale factor when created and whenever it changes,
+    /// so set it yourself only for headless rendering.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.dirty = true;
+        self.redraw_requested = true;
+    }
+
+    /// Physical pixels per logical pixel, e.g. 2 on a HiDPI display. See [Self::set_scale_factor].
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// Drawable size in physical pixels of the window showing the scene, which the root node fills.
+    /// Updated on resize before the next frame's update handler runs, and kept while the window is minimized.
+    /// Zero until the scene is shown in a window.
+    pub fn screen_size(&self) -> Dimension {
+        self.screen_size
+    }
+
+    /// Redraw every frame instead of only when the window needs it (resize, input, animation).
+    pub fn set_continuous(&mut self, continuous: bool) {
+        self.continuous = continuous;
+    }
+
+    /// Redraw the window once more after the current frame or event, e.g. after changing the scene in the update handler.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    // Whether another frame has to be drawn. Clears the single redraw request.
+    pub(crate) fn take_redraw(&mut self) -> bool {
+        let redraw = self.continuous || self.has_animations() || self.redraw_requested;
+        self.redraw_requested = false;
+        redraw
+    }
+
+    /// Number of nodes created in the scene, whether reachable from the root or not.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Root node set with [crate::Guiug::set_root], if any.
+    pub fn root(&self) -> Option<NodeId> {
+        self.root_node
+    }
+
+    /// Children of the node in order, to walk the tree without matching on every node kind.
+    /// Empty for display nodes and missing nodes.
+    pub fn children_of(&self, node_id: NodeId) -> Vec<NodeId> {
+        self.get_node(&node_id)
+            .map_or_else(Vec::new, Node::children)
+    }
+
+    /// Text outline of the tree from the root, one node per line indented by its depth, for debugging.
+    /// Each line shows the node's id and kind, preceded by its position in the parent: the [Position] in a Layer and
+    /// the [Size] in a Row or Column. Hidden nodes are marked, and a node reached again through its own subtree is
+    /// marked as a cycle without listing its children.
+    pub fn dump_tree(&self) -> String {
+        let mut dump = String::new();
+        match self.root_node {
+            Some(root) => self.dump_node(&mut dump, root, None, &mut Vec::new()),
+            None => dump.push_str("(no root)\n"),
+        }
+        dump
+    }
+
+    fn dump_node(
+        &self,
+        dump: &mut String,
+        node_id: NodeId,
+        position: Option<String>,
+        ancestors: &mut Vec<NodeId>,
+    ) {
+        use std::fmt::Write;
+
+        let indent = "  ".repeat(ancestors.len());
+        let position = position
+            .map(|position| format!("{position} "))
+            .unwrap_or_default();
+        let Some(node) = self.get_node(&node_id) else {
+            let _ = writeln!(dump, "{indent}{position}#{node_id} (missing)");
+            return;
+        };
+        let kind = match node {
+            Node::Layer { clip: true, .. } => "Layer (clip)",
+            Node::Layer { .. } => "Layer",
+            Node::Row { .. } => "Row",
+            Node::Column { .. } => "Column",
+            Node::Scroll { .. } => "Scroll",
+            Node::Padding { .. } => "Padding",
+            Node::Opacity { .. } => "Opacity",
+            Node::Shadow { .. } => "Shadow",
+            Node::Rect { .. } => "Rect",
+            Node::RoundedRect { .. } => "RoundedRect",
+            Node::BorderedRect { .. } => "BorderedRect",
+            Node::NinePatch { .. } => "NinePatch",
+            Node::Gradient { .. } => "Gradient",
+            Node::Line { .. } => "Line",
+            Node::Circle { .. } => "Circle",
+            Node::Texture { .. } => "Texture",
+            Node::Text { .. } => "Text",
+            Node::Empty => "Empty",
+        };
+        let hidden = if self.hidden.contains(&node_id) {
+            " (hidden)"
+        } else {
+            ""
+        };
+        if ancestors.contains(&node_id) {
+            let _ = writeln!(dump, "{indent}{position}#{node_id} {kind} (cycle)");
+            return;
+        }
+        let _ = writeln!(dump, "{indent}{position}#{node_id} {kind}{hidden}");
+
+        ancestors.push(node_id);
+        match node {
+            Node::Layer { inner, .. } => {
+                for (position, child) in inner {
+                    self.dump_node(dump, *child, Some(format!("{position:?}")), ancestors);
+                }
+            }
+            Node::Row { inner, .. } | Node::Column { inner, .. } => {
+                for (size, child) in inner {
+                    self.dump_node(dump, *child, Some(format!("{size:?}")), ancestors);
+                }
+            }
+            Node::Padding { child, .. }
+            | Node::Scroll { child }
+            | Node::Opacity { child, .. }
+            | Node::Shadow { child, .. } => self.dump_node(dump, *child, None, ancestors),
+            _ => (),
+        }
+        ancestors.pop();
+    }
+
+    /// Check that the tree from the root has no cycle, no missing node and does not exceed the max depth.
+    /// Rendering skips the offending subtrees, so use this to find out why something is missing.
+    pub fn validate(&self) -> Result<(), SceneError> {
+        let visitor = NodeVisitor::visit(Dimension::new(0, 0), self);
+        match visitor.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
     // Allocate new NodeId and associate the given node to it. Created NodeId will be returned.
     pub(crate) fn insert_node(&mut self, node: Node) -> NodeId {
         let id = self.last_id;
         self.last_id += 1;
         self.nodes.entry(id).insert_entry(node);
+        self.dirty = true;
         id
     }
 
     pub(crate) fn get_node(&self, id: &NodeId) -> Option<&Node> {
         self.nodes.get(id)
     }
+
+    /// Aspect ratio of the shown region of a Texture node whose texture size is known.
+    pub(crate) fn node_aspect(&self, node_id: NodeId) -> Option<Aspect> {
+        let Some(Node::Texture {
+            texture_id,
+            uv_min,
+            uv_max,
+            ..
+        }) = self.get_node(&node_id)
+        else {
+            return None;
+        };
+        let (width, height) = self.texture_size(*texture_id)?;
+        let region = (*uv_max - *uv_min).abs() * Vec2::new(width as f32, height as f32);
+        (region.y > 0.0).then(|| Aspect(region.x / region.y))
+    }
+
+    /// Find the topmost node drawn at the given screen point, using the same layout as rendering.
+    /// Nodes that draw something can be hit; Layer, Row, Column and Padding containers are transparent to hits.
+    /// Parts of a node clipped away by a clipping layer are not hit.
+    /// * `point` - (x, y) pixel position from the top-left corner of the screen.
+    /// * `screen` - size of the screen to lay the scene out on.
+    pub fn node_at(&self, point: (i32, i32), screen: Dimension) -> Option<NodeId> {
+        let visitor = NodeVisitor::visit(screen, self);
+        visitor
+            .drawn_nodes
+            .iter()
+            .filter(|(_, rect, _)| rect.contains(point.0, point.1))
+            .min_by_key(|(_, _, depth)| *depth)
+            .map(|(node_id, _, _)| *node_id)
+    }
+
+    /// Resolved rect of the node laid out on the `screen`, using the same layout as rendering.
+    /// The rect is not clipped and ignores rotation. If the node is reached through several parents, the first one in
+    /// traversal order is used.
+    /// Returns `None` if the node is not reachable from the root.
+    pub fn layout_rect(&self, node_id: NodeId, screen: Dimension) -> Option<BoundingBox> {
+        let visitor = NodeVisitor::visit(screen, self);
+        visitor
+            .layout_rects
+            .iter()
+            .find(|(id, _)| *id == node_id)
+            .map(|(_, rect)| BoundingBox::from(*rect))
+    }
+
+    /// Export the scene laid out on a `width` x `height` screen as an SVG document.
+    /// Only textures added with [Scene::add_texture_rgba8] are embedded, as the builder owns the others; use [crate::Guiug::to_svg] to embed them too.
+    /// Shader effects are not reproduced.
+    pub fn to_svg(&self, width: i32, height: i32) -> String {
+        crate::svg::render(self, None, Dimension::new(width, height))
+    }
+
+    /// Export the scene laid out on a `width` x `height` screen as a single page PDF document at `path`,
+    /// one point per pixel. Textures are embedded as in [Scene::to_svg]; use [crate::Guiug::to_pdf] to embed all of them.
+    /// Shader effects are not reproduced.
+    #[cfg(feature = "pdf")]
+    pub fn to_pdf(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        width: i32,
+        height: i32,
+    ) -> std::io::Result<()> {
+        let pdf = crate::pdf::render(self, None, Dimension::new(width, height));
+        std::fs::write(path, pdf)
+    }
+
+    /// Register a texture from tightly packed sRGB-encoded RGBA pixels, e.g. generated procedurally.
+    /// The pixels are uploaded as they are, without the image decoder, before the next frame is drawn.
+    /// Fails if `pixels` is not `width * height * 4` bytes long.
+    pub fn add_texture_rgba8(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    ) -> Result<texture::TextureId, texture::TextureError> {
+        let texture_info = texture::TextureInfo::rgba8(width, height, pixels)?;
+        let id = self.next_texture_id();
+        self.texture_infos.insert(id, texture_info);
+        self.pending_textures.push(id);
+        self.redraw_requested = true;
+        Ok(id)
+    }
+
+    /// Replace the pixels of a texture, e.g. a live plot or a video frame, with tightly packed sRGB-encoded RGBA pixels.
+    /// The GPU allocation is reused, so the size must match the texture's; a mismatch is reported in [Self::texture_errors]
+    /// when the pixels are uploaded before the next frame.
+    pub fn update_texture_rgba8(
+        &mut self,
+        texture_id: texture::TextureId,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    ) -> Result<(), texture::TextureError> {
+        if texture_id >= self.last_texture_id {
+            return Err(texture::TextureError::NotFound(texture_id));
+        }
+        let texture_info = texture::TextureInfo::rgba8(width, height, pixels)?;
+        self.texture_infos.insert(texture_id, texture_info);
+        if !self.pending_textures.contains(&texture_id) {
+            self.pending_textures.push(texture_id);
+        }
+        self.redraw_requested = true;
+        Ok(())
+    }
+
+    /// Set the TrueType or OpenType font of every Text node, replacing the previous one.
+    /// Text nodes draw nothing until a font is set. The font is not serialized with the scene, so set it again after loading.
+    /// Glyphs are rasterized into an atlas texture as text using them is added, which takes one texture id.
+    pub fn set_font(&mut self, data: Vec<u8>) -> Result<(), text::FontError> {
+        let texture_id = match &self.font {
+            Some(font) => font.texture_id,
+            None => self.next_texture_id(),
+        };
+        let mut font = text::Font::new(data, texture_id)?;
+        for node in self.nodes.values() {
+            if let Node::Text { text, .. } = node {
+                font.add_glyphs(text);
+            }
+        }
+        let texture_info =
+            texture::TextureInfo::rgba8(text::ATLAS_SIZE, text::ATLAS_SIZE, font.pixels().to_vec())
+                .expect("atlas pixels match the atlas size");
+        self.texture_infos.insert(texture_id, texture_info);
+        if !self.pending_textures.contains(&texture_id) {
+            self.pending_textures.push(texture_id);
+        }
+        self.font = Some(font);
+        self.dirty = true;
+        self.redraw_requested = true;
+        Ok(())
+    }
+
+    /// Width and height in pixels of `text` drawn by a Text node of the given `size`, without drawing it, e.g. to size
+    /// a button around its label. The width spans the widest line up to the advance of its last glyph, and the height
+    /// every line from the ascent of the first to the descent of the last, so it does not depend on the glyphs used.
+    /// `size` resolves against the `screen`, as it would for a Text node filling the screen.
+    /// Both are 0 if no font is set.
+    pub fn measure_text(&self, text: &str, size: Size, screen: Dimension) -> Dimension {
+        self.text_size(text, &size, screen, screen)
+            .unwrap_or(Dimension::new(0, 0))
+    }
+
+    // Measured size of the text of a Text node whose `size` resolves against `parent_size`. `None` if no font is set.
+    fn text_size(
+        &self,
+        text: &str,
+        size: &Size,
+        parent_size: Dimension,
+        screen: Dimension,
+    ) -> Option<Dimension> {
+        let font = self.font.as_ref()?;
+        let size = size.resolve(parent_size, screen, self.scale_factor);
+        let (width, height) = font.measure(text, size as f32);
+        Some(Dimension::new(width.ceil() as i32, height.ceil() as i32))
+    }
+
+    /// Size of the node's content that [Size::FitContent] resolves to, laid out in a parent of `parent_size`.
+    /// `None` for nodes without intrinsic size.
+    pub(crate) fn content_size(
+        &self,
+        mut node_id: NodeId,
+        parent_size: Dimension,
+        screen: Dimension,
+    ) -> Option<Dimension> {
+        // insets of the Padding nodes wrapping the content
+        let mut padding = Dimension::new(0, 0);
+        // bounded, as Padding nodes may form a cycle
+        for _ in 0..self.max_depth {
+            if !self.is_visible(node_id) {
+                return None;
+            }
+            let content = match self.get_node(&node_id)? {
+                Node::Text { text, size, .. } => self.text_size(text, size, parent_size, screen)?,
+                Node::Texture {
+                    texture_id,
+                    uv_min,
+                    uv_max,
+                    ..
+                } => {
+                    let (width, height) = self.texture_size(*texture_id)?;
+                    let region = (*uv_max - *uv_min).abs() * Vec2::new(width as f32, height as f32);
+                    Dimension::new(region.x.round() as i32, region.y.round() as i32)
+                }
+                Node::Padding { insets, child } => {
+                    let [left, top, right, bottom] = insets
+                        .each_ref()
+                        .map(|inset| inset.resolve(parent_size, screen, self.scale_factor));
+                    padding.width += left + right;
+                    padding.height += top + bottom;
+                    node_id = *child;
+                    continue;
+                }
+                Node::Opacity { child, .. } | Node::Shadow { child, .. } => {
+                    node_id = *child;
+                    continue;
+                }
+                _ => return None,
+            };
+            return Some(Dimension::new(
+                content.width + padding.width,
+                content.height + padding.height,
+            ));
+        }
+        None
+    }
+
+    // Rasterize the glyphs of `text` missing from the font atlas, and upload the atlas if it changed.
+    pub(crate) fn add_glyphs(&mut self, text: &str) {
+        let Some(font) = &mut self.font else {
+            return;
+        };
+        if font.add_glyphs(text) {
+            let (texture_id, pixels) = (font.texture_id, font.pixels().to_vec());
+            let _ =
+                self.update_texture_rgba8(texture_id, text::ATLAS_SIZE, text::ATLAS_SIZE, pixels);
+        }
+    }
+
+    /// Width and height in pixels of a texture, e.g. to size its node with the right aspect ratio.
+    /// Encoded images have their size read from the header when registered. `None` if the texture is unknown or not a decodable image.
+    pub fn texture_size(&self, texture_id: texture::TextureId) -> Option<(u32, u32)> {
+        self.texture_sizes
+            .get(&texture_id)
+            .copied()
+            .or_else(|| self.texture_infos.get(texture_id)?.size())
+    }
+
+    pub(crate) fn next_texture_id(&mut self) -> texture::TextureId {
+        let id = self.last_texture_id;
+        self.last_texture_id += 1;
+        id
+    }
+
+    /// Textures that failed to decode when the window started, with the reason.
+    /// Texture nodes using them draw nothing. Use [crate::Guiug::try_add_texture] to catch bad data before running.
+    pub fn texture_errors(&self) -> &[(texture::TextureId, texture::TextureError)] {
+        &self.texture_errors
+    }
+
+    /// Save the next frame the window renders as a PNG image at `path`, e.g. from a key press handler.
+    /// The frame is drawn again offscreen and read back, which stalls that frame. Requesting another screenshot before
+    /// then replaces the path. Failures are reported in [Self::screenshot_errors].
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.screenshot_request = Some(path.into());
+        self.redraw_requested = true;
+    }
+
+    /// Screenshots requested with [Self::request_screenshot] that could not be saved, with the reason.
+    pub fn screenshot_errors(&self) -> &[(PathBuf, GuiugError)] {
+        &self.screenshot_errors
+    }
+
+    /// Whether the window renders with a software fallback adapter (e.g. llvmpipe) because no hardware
+    /// adapter was found. Expect lower performance.
+    pub fn uses_fallback_adapter(&self) -> bool {
+        self.fallback_adapter
+    }
+
+    /// MSAA samples per pixel the window renders with, chosen from [crate::GpuConfig::sample_count] when the window
+    /// starts. 1 before that.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Statistics of the last rendered frame.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Number of draw calls issued in the last rendered frame.
+    /// Useful to check that batching actually reduces the number of calls.
+    pub fn draw_call_count(&self) -> u32 {
+        self.frame_stats.draw_calls
+    }
+
+    // Find the position of the node inside its parent layer.
+    pub(crate) fn position(&self, id: NodeId) -> Option<&Position> {
+        self.nodes.values().find_map(|node| match node {
+            Node::Layer { inner, .. } => inner
+                .iter()
+                .find(|(_, child_id)| *child_id == id)
+                .map(|(position, _)| position),
+            _ => None,
+        })
+    }
+
+    // Like [Self::position], to change it. The caller marks the scene dirty once it writes.
+    pub(crate) fn position_mut(&mut self, id: NodeId) -> Option<&mut Position> {
+        self.nodes.values_mut().find_map(|node| match node {
+            Node::Layer { inner, .. } => inner
+                .iter_mut()
+                .find(|(_, child_id)| *child_id == id)
+                .map(|(position, _)| position),
+            _ => None,
+        })
+    }
+
+    /// Append `child` to the Layer node `layer`.
+    /// Fails if `layer` is not a Layer node, if `child` does not exist, or if `child` is `layer` itself or one of its ancestors.
+    pub fn layer_insert(
+        &mut self,
+        layer: NodeId,
+        position: Position,
+        child: NodeId,
+    ) -> Result<(), SceneError> {
+        if !matches!(self.get_node(&layer), Some(Node::Layer { .. })) {
+            return Err(SceneError::NotLayer { node_id: layer });
+        }
+        if self.get_node(&child).is_none() {
+            return Err(SceneError::NotFound { node_id: child });
+        }
+        if self.is_descendant(child, layer) {
+            return Err(SceneError::WouldCycle {
+                parent: layer,
+                child,
+            });
+        }
+        if let Some(Node::Layer { inner, .. }) = self.nodes.get_mut(&layer) {
+            inner.push((position, child));
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Rotate the node and its descendants by `radians` about the node's center, clockwise on the screen.
+    /// Default is 0. Layout, clipping and [Self::node_at] ignore the rotation, so they use the unrotated rects.
+    pub fn set_rotation(&mut self, node_id: NodeId, radians: f32) {
+        self.dirty = true;
+        if radians == 0.0 {
+            self.rotations.remove(&node_id);
+        } else {
+            self.rotations.insert(node_id, radians);
+        }
+    }
+
+    /// Rotation of the node in radians, set with [Self::set_rotation].
+    pub fn rotation(&self, node_id: NodeId) -> f32 {
+        self.rotations.get(&node_id).copied().unwrap_or(0.0)
+    }
+
+    /// Set how the node and its descendants combine with what is drawn behind them, e.g. [BlendMode::Additive] for a
+    /// glow. A descendant's own blend mode overrides it. Default is [BlendMode::Replace].
+    /// The SVG and PDF exporters draw every node as Replace.
+    pub fn set_blend_mode(&mut self, node_id: NodeId, mode: BlendMode) {
+        self.dirty = true;
+        if mode == BlendMode::Replace {
+            self.blend_modes.remove(&node_id);
+        } else {
+            self.blend_modes.insert(node_id, mode);
+        }
+    }
+
+    /// Blend mode of the node itself, set with [Self::set_blend_mode]. Inherited modes are not included.
+    pub fn blend_mode(&self, node_id: NodeId) -> BlendMode {
+        self.blend_modes.get(&node_id).copied().unwrap_or_default()
+    }
+
+    /// Show or hide the node and its descendants, keeping them in the scene with their ids. Default is visible.
+    /// A hidden node is skipped entirely: nothing in its subtree is drawn or hit by [Self::node_at], [Self::layout_rect]
+    /// returns `None` for it, and a Row or Column lays its other children out as if it were removed, gap included.
+    /// In a Layer, the other children keep their positions.
+    pub fn set_visible(&mut self, node_id: NodeId, visible: bool) {
+        self.dirty = true;
+        if visible {
+            self.hidden.remove(&node_id);
+        } else {
+            self.hidden.insert(node_id);
+        }
+    }
+
+    /// Whether the node itself is visible, set with [Self::set_visible]. Hidden ancestors are not considered.
+    pub fn is_visible(&self, node_id: NodeId) -> bool {
+        !self.hidden.contains(&node_id)
+    }
+
+    // Children of a Row or Column that take space.
+    pub(crate) fn visible_children<'a>(
+        &self,
+        inner: &'a [(Size, NodeId)],
+    ) -> Vec<&'a (Size, NodeId)> {
+        inner
+            .iter()
+            .filter(|(_, child)| self.is_visible(*child))
+            .collect()
+    }
+
+    /// Scroll the Scroll node `node_id` down by `offset` pixels from the top of its content. Default is 0.
+    /// Negative offsets are stored as 0. Offsets past the end of the content are clamped when laid out,
+    /// as the content height depends on the screen size.
+    pub fn set_scroll_offset(&mut self, node_id: NodeId, offset: i32) {
+        self.dirty = true;
+        if offset <= 0 {
+            self.scroll_offsets.remove(&node_id);
+        } else {
+            self.scroll_offsets.insert(node_id, offset);
+        }
+    }
+
+    /// Scroll offset of the node in pixels, set with [Self::set_scroll_offset] or by scrolling.
+    pub fn scroll_offset(&self, node_id: NodeId) -> i32 {
+        self.scroll_offsets.get(&node_id).copied().unwrap_or(0)
+    }
+
+    /// Scroll the innermost Scroll node under `point` down by `delta` pixels, up if negative, keeping its offset
+    /// between 0 and the content height minus its own height.
+    /// Returns the scrolled node, or `None` if no Scroll node is under the point.
+    /// * `point` - (x, y) pixel position from the top-left corner of the screen.
+    /// * `screen` - size of the screen to lay the scene out on.
+    pub fn scroll_at(
+        &mut self,
+        point: (i32, i32),
+        delta: i32,
+        screen: Dimension,
+    ) -> Option<NodeId> {
+        let visitor = NodeVisitor::visit(screen, self);
+        // nested Scroll nodes are visited after their ancestors
+        let (node_id, _, max_offset) = visitor
+            .scroll_ranges
+            .iter()
+            .rev()
+            .find(|(_, rect, _)| rect.contains(point.0, point.1))?;
+        let offset = self.scroll_offset(*node_id).min(*max_offset);
+        self.set_scroll_offset(*node_id, (offset + delta).clamp(0, *max_offset));
+        Some(*node_id)
+    }
+
+    /// Mirror the Texture node `node_id` horizontally (`flip_x`) and/or vertically (`flip_y`),
+    /// e.g. to face a sprite left or right with one image. Applies to the sprite region of [crate::Guiug::sprite_node] too.
+    /// Fails if `node_id` is not a Texture node.
+    pub fn set_texture_flip(
+        &mut self,
+        node_id: NodeId,
+        flip_x: bool,
+        flip_y: bool,
+    ) -> Result<(), SceneError> {
+        match self.nodes.get_mut(&node_id) {
+            Some(Node::Texture {
+                flip_x: node_flip_x,
+                flip_y: node_flip_y,
+                ..
+            }) => {
+                *node_flip_x = flip_x;
+                *node_flip_y = flip_y;
+                self.dirty = true;
+                Ok(())
+            }
+            _ => Err(SceneError::NotTexture { node_id }),
+        }
+    }
+
+    /// Set the alpha the opacity group node `node_id` composites its subtree at, e.g. to fade a panel in or out.
+    /// Fails if `node_id` is not an opacity group node, see [crate::Guiug::group_opacity_node].
+    pub fn set_group_opacity(&mut self, node_id: NodeId, alpha: f32) -> Result<(), SceneError> {
+        match self.nodes.get_mut(&node_id) {
+            Some(Node::Opacity {
+                alpha: node_alpha, ..
+            }) => {
+                *node_alpha = alpha;
+                self.dirty = true;
+                Ok(())
+            }
+            _ => Err(SceneError::NotOpacityGroup { node_id }),
+        }
+    }
+
+    // Color of the node animated by `animate_color`.
+    pub(crate) fn color(&self, node_id: NodeId) -> Option<&Vec4> {
+        match self.nodes.get(&node_id)? {
+            Node::Rect { color }
+            | Node::RoundedRect { color, .. }
+            | Node::Circle { color }
+            | Node::Line { color, .. }
+            | Node::Text { color, .. }
+            | Node::Shadow { color, .. }
+            | Node::BorderedRect { fill: color, .. }
+            | Node::Texture { tint: color, .. } => Some(color),
+            _ => None,
+        }
+    }
+
+    // Like [Self::color], to change it. The caller marks the scene dirty once it writes.
+    pub(crate) fn color_mut(&mut self, node_id: NodeId) -> Option<&mut Vec4> {
+        match self.nodes.get_mut(&node_id)? {
+            Node::Rect { color }
+            | Node::RoundedRect { color, .. }
+            | Node::Circle { color }
+            | Node::Line { color, .. }
+            | Node::Text { color, .. }
+            | Node::Shadow { color, .. }
+            | Node::BorderedRect { fill: color, .. }
+            | Node::Texture { tint: color, .. } => Some(color),
+            _ => None,
+        }
+    }
+
+    /// Move `child` out of every Layer node containing it into the Layer node `new_layer`.
+    /// Fails without changing the scene if the move would create a cycle.
+    pub fn reparent(
+        &mut self,
+        child: NodeId,
+        new_layer: NodeId,
+        position: Position,
+    ) -> Result<(), SceneError> {
+        if !matches!(self.get_node(&new_layer), Some(Node::Layer { .. })) {
+            return Err(SceneError::NotLayer { node_id: new_layer });
+        }
+        if self.is_descendant(child, new_layer) {
+            return Err(SceneError::WouldCycle {
+                parent: new_layer,
+                child,
+            });
+        }
+        for node in self.nodes.values_mut() {
+            if let Node::Layer { inner, .. } = node {
+                inner.retain(|(_, child_id)| *child_id != child);
+            }
+        }
+        self.layer_insert(new_layer, position, child)
+    }
+
+    // Whether `node` is `ancestor` itself or reachable from it.
+    pub(crate) fn is_descendant(&self, ancestor: NodeId, node: NodeId) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![ancestor];
+        while let Some(current) = stack.pop() {
+            if current == node {
+                return true;
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            stack.extend(self.children_of(current));
+        }
+        false
+    }
+
+    /// Move the node along the polyline `points` over `duration` seconds.
+    /// The node must be placed inside a Layer node. Each point is written to the `pos` of the node's
+    /// horizontal and vertical anchors as [Size::Pixel], so its meaning follows the anchor kind
+    /// (offset from start, center or end). [Anchor::Stretch] axes are left untouched.
+    /// The node moves at uniform speed along the path regardless of segment lengths.
+    pub fn animate_along_path(
+        &mut self,
+        id: NodeId,
+        points: Vec<Vec2>,
+        duration: f32,
+        easing: Easing,
+    ) -> AnimationId {
+        let animation =
+            Animation::new(id, AnimationKind::Path(Path::new(points)), duration, easing);
+        self.animator.insert(animation)
+    }
+
+    /// Move the node from its current position in its Layer to `to` over `duration` seconds.
+    /// Each anchor of `to` must be of the same kind (start, center, end or stretch) as the current one, and each of
+    /// their sizes the same [Size] variant, of the same texture for [Size::AspectOf] and the same number of terms for
+    /// [Size::Sum]; the values in between are interpolated, and [Size::Pixel] ones rounded.
+    /// [Position::z_index] switches to the target's when the animation ends.
+    /// Fails if the node is not in a Layer node, or if the positions are of different kinds.
+    pub fn animate_position(
+        &mut self,
+        id: NodeId,
+        to: Position,
+        duration: f32,
+        easing: Easing,
+    ) -> Result<AnimationId, SceneError> {
+        let from = self
+            .position(id)
+            .ok_or(SceneError::NotInLayer { node_id: id })?
+            .clone();
+        if from.lerp(&to, 0.0).is_none() {
+            return Err(SceneError::PositionMismatch { node_id: id });
+        }
+        let animation = Animation::new(
+            id,
+            AnimationKind::Position {
+                from: Box::new(from),
+                to: Box::new(to),
+            },
+            duration,
+            easing,
+        );
+        Ok(self.animator.insert(animation))
+    }
+
+    /// Fade the color of the node from its current one to `to` over `duration` seconds: the color of Rect,
+    /// RoundedRect, Circle, Line, Text and Shadow nodes, the fill of BorderedRect nodes and the tint of Texture nodes.
+    /// Colors are interpolated as given, in linear space unless set otherwise with [Self::set_color_space].
+    /// Fails if the node has no such color.
+    pub fn animate_color(
+        &mut self,
+        id: NodeId,
+        to: Vec4,
+        duration: f32,
+        easing: Easing,
+    ) -> Result<AnimationId, SceneError> {
+        let from = *self.color(id).ok_or(SceneError::NoColor { node_id: id })?;
+        let animation = Animation::new(id, AnimationKind::Color { from, to }, duration, easing);
+        Ok(self.animator.insert(animation))
+    }
+
+    /// Restart the animation from the beginning every time it ends, instead of finishing.
+    pub fn set_animation_looping(&mut self, animation_id: AnimationId, looping: bool) {
+        if let Some(animation) = self.animator.get_mut(animation_id) {
+            animation.looping = looping;
+        }
+    }
+
+    /// Set callback invoked once when the animation finishes. Looping animations never finish.
+    pub fn on_animation_complete(
+        &mut self,
+        animation_id: AnimationId,
+        callback: impl FnOnce(&mut Scene) + 'static,
+    ) {
+        if let Some(animation) = self.animator.get_mut(animation_id) {
+            animation.on_complete = Some(Box::new(callback));
+        }
+    }
+
+    pub(crate) fn has_animations(&self) -> bool {
+        !self.animator.is_empty()
+    }
+
+    // Advance running animations by `dt` seconds and apply them to the nodes.
+    pub(crate) fn advance_animations(&mut self, dt: f32) {
+        let (updates, callbacks) = self.animator.advance(dt);
+        for update in updates {
+            match update {
+                AnimationUpdate::Offset(id, offset) => {
+                    if let Some(position) = self.position_mut(id) {
+                        position
+                            .horizontal
+                            .set_pos(Size::Pixel(offset.x.round() as i32));
+                        position
+                            .vertical
+                            .set_pos(Size::Pixel(offset.y.round() as i32));
+                        self.dirty = true;
+                    }
+                }
+                AnimationUpdate::Position(id, new_position) => {
+                    if let Some(position) = self.position_mut(id) {
+                        *position = new_position;
+                        self.dirty = true;
+                    }
+                }
+                AnimationUpdate::Color(id, new_color) => {
+                    if let Some(color) = self.color_mut(id) {
+                        *color = new_color;
+                        self.dirty = true;
+                    }
+                }
+            }
+        }
+        for callback in callbacks {
+            callback(self);
+        }
+    }
+}
+
+/// Rendering statistics of a single frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// Number of `draw_indexed` calls.
+    pub draw_calls: u32,
+    /// Number of rect instances, opaque and translucent.
+    pub rect_instances: u32,
+    /// Number of texture instances.
+    pub texture_instances: u32,
 }
 
 /// Node in the scene tree.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     // Container nodes
-    Layer { inner: Vec<(Position, NodeId)> },
-    Row { inner: Vec<(Size, NodeId)> },
-    Column { inner: Vec<(Size, NodeId)> },
+    /// * `clip` - restrict drawing of the descendants to the layer's rect.
+    Layer {
+        inner: Vec<(Position, NodeId)>,
+        clip: bool,
+    },
+    /// * `gap` - space between consecutive children, resolved against the node's own size.
+    /// * `align` - horizontal placement of the children.
+    Row {
+        inner: Vec<(Size, NodeId)>,
+        gap: Size,
+        align: CrossAlign,
+    },
+    /// * `gap` - space between consecutive children, resolved against the node's own size.
+    /// * `align` - vertical placement of the children.
+    Column {
+        inner: Vec<(Size, NodeId)>,
+        gap: Size,
+        align: CrossAlign,
+    },
+    /// Lays `child` out in the node's rect moved up by the node's scroll offset, clipped to the node's rect.
+    Scroll {
+        child: NodeId,
+    },
+    /// Lays `child` out in the node's rect shrunk by `insets`, the left, top, right and bottom spacing,
+    /// resolved against the node's own size. Negative insets grow the child's rect past the node's.
+    Padding {
+        insets: [Size; 4],
+        child: NodeId,
+    },
+    /// Lays `child` out in the node's rect, draws the subtree into an offscreen target and composites the target
+    /// at `alpha`, so the subtree fades as a whole instead of its overlapping nodes showing through each other.
+    Opacity {
+        alpha: f32,
+        child: NodeId,
+    },
+    /// Lays `child` out in the node's rect, over a soft shadow of the rect moved by `offset`.
+    /// The shadow fades out from `color` across `blur` pixels on each side of its edge.
+    /// `offset` and `blur` resolve against the node's own size.
+    Shadow {
+        child: NodeId,
+        offset: (Size, Size),
+        blur: Size,
+        color: Vec4,
+    },
 
     // Display nodes
-    Rect { color: Vec4 },
-    Texture { texture_id: texture::TextureId },
+    Rect {
+        color: Vec4,
+    },
+    /// Rect with rounded corners. `radius` resolves against the node's own size, so
+    /// `ParentWidth`/`ParentHeight` are ratios of the node's width/height.
+    RoundedRect {
+        color: Vec4,
+        radius: Size,
+    },
+    /// Rect with a solid border drawn inside its edge. `width` resolves against the node's own size.
+    BorderedRect {
+        fill: Vec4,
+        border: Vec4,
+        width: Size,
+    },
+    /// Texture stretched with fixed corners. `insets` are the top, right, bottom and left border widths,
+    /// resolved against the node's own size.
+    NinePatch {
+        texture_id: texture::TextureId,
+        insets: [Size; 4],
+    },
+    /// Rect filled with a linear gradient from `start` to `end` color.
+    /// * `angle` - direction in radians in the node's local space: 0 runs left to right, PI/2 top to bottom.
+    Gradient {
+        start: Vec4,
+        end: Vec4,
+        angle: f32,
+    },
+    /// Line through `points` with bevel joins and flat ends. Points resolve against the node's own size, from its
+    /// top-left corner, and so does `width`.
+    Line {
+        points: Vec<(Size, Size)>,
+        width: Size,
+        color: Vec4,
+    },
+    /// Ellipse inscribed in the node's rect, a circle if the rect is square.
+    Circle {
+        color: Vec4,
+    },
+    /// * `tint` - multiplies each texel. White leaves the texture unchanged.
+    /// * `uv_min`, `uv_max` - region of the texture shown, in normalized texture coordinates
+    ///   from (0, 0) at the top-left to (1, 1) at the bottom-right.
+    /// * `flip_x`, `flip_y` - mirror the shown region horizontally/vertically.
+    Texture {
+        texture_id: texture::TextureId,
+        tint: Vec4,
+        uv_min: Vec2,
+        uv_max: Vec2,
+        flip_x: bool,
+        flip_y: bool,
+    },
+    /// Single line of text per `\n`, laid out left to right from the node's top-left corner with the scene's font,
+    /// see [Scene::set_font]. Text past the node's rect is not clipped.
+    /// * `size` - line height from the top of the tallest to the bottom of the lowest glyph,
+    ///   resolved against the node's own size.
+    Text {
+        text: String,
+        size: Size,
+        color: Vec4,
+    },
     Empty,
 }
 
+impl Node {
+    /// Children of a container node in order, empty for display nodes.
+    pub fn children(&self) -> Vec<NodeId> {
+        match self {
+            Node::Layer { inner, .. } => inner.iter().map(|(_, child)| *child).collect(),
+            Node::Row { inner, .. } | Node::Column { inner, .. } => {
+                inner.iter().map(|(_, child)| *child).collect()
+            }
+            Node::Scroll { child }
+            | Node::Padding { child, .. }
+            | Node::Opacity { child, .. }
+            | Node::Shadow { child, .. } => vec![*child],
+            _ => Vec::new(),
+        }
+    }
+}
+
 /// Position and size of the node.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     pub horizontal: Anchor,
     pub vertical: Anchor,
+
+    /// Stacking order of the node among the nodes of the whole scene. Higher z_index draws on top.
+    /// It is relative to the parent layer: z_index of nested layers are added together.
+    /// Nodes with the same z_index are painted in scene-tree order: a later child of a layer, and everything under
+    /// it, draws on top of the earlier children, as in a painter's algorithm.
+    ///
+    /// Any i32 is valid. Distinct stacking levels are ranked and spread over the 0~1 range of the
+    /// depth buffer compared with `Less`, so up to 2^24 levels stay distinguishable. Without a depth buffer, see
+    /// [crate::DepthConfig::None], textured nodes draw above the others regardless of their z_index.
+    pub z_index: i32,
 }
 
 impl Position {
     pub const FULL: Self = Self {
         horizontal: Anchor::stretch(Size::ZERO, Size::ZERO),
         vertical: Anchor::stretch(Size::ZERO, Size::ZERO),
+        z_index: 0,
     };
 
     pub const fn new(horizontal: Anchor, vertical: Anchor) -> Self {
         Self {
             horizontal,
             vertical,
+            z_index: 0,
         }
     }
 
-    pub(crate) fn apply(&self, parent_rect: Rect, screen_size: Dimension) -> Rect {
+    /// Set [Self::z_index].
+    pub fn with_z_index(self, z_index: i32) -> Self {
+        Self { z_index, ..self }
+    }
+
+    /// Square node of `size` width and height centered in the parent.
+    pub fn centered(size: Size) -> Self {
+        Self::new(
+            Anchor::center(Size::ZERO, size.clone()),
+            Anchor::center(Size::ZERO, size),
+        )
+    }
+
+    /// Node of `w` x `h` at the top-left corner of the parent.
+    pub const fn top_left(w: Size, h: Size) -> Self {
+        Self::new(Anchor::start(Size::ZERO, w), Anchor::start(Size::ZERO, h))
+    }
+
+    /// Node filling the parent, inset by `margin` on every side.
+    pub fn fill_with_margin(margin: Size) -> Self {
+        Self::new(
+            Anchor::stretch(margin.clone(), margin.clone()),
+            Anchor::stretch(margin.clone(), margin),
+        )
+    }
+
+    /// Bar glued to the top edge of the parent, spanning its full width.
+    /// * `thickness` - height of the bar.
+    pub const fn top_bar(thickness: Size) -> Self {
+        Self::new(
+            Anchor::stretch(Size::ZERO, Size::ZERO),
+            Anchor::start(Size::ZERO, thickness),
+        )
+    }
+
+    /// Bar glued to the bottom edge of the parent, spanning its full width.
+    /// * `thickness` - height of the bar.
+    pub const fn bottom_bar(thickness: Size) -> Self {
+        Self::new(
+            Anchor::stretch(Size::ZERO, Size::ZERO),
+            Anchor::end(Size::ZERO, thickness),
+        )
+    }
+
+    /// Rail glued to the left edge of the parent, spanning its full height.
+    /// * `thickness` - width of the rail.
+    pub const fn left_rail(thickness: Size) -> Self {
+        Self::new(
+            Anchor::start(Size::ZERO, thickness),
+            Anchor::stretch(Size::ZERO, Size::ZERO),
+        )
+    }
+
+    /// Rail glued to the right edge of the parent, spanning its full height.
+    /// * `thickness` - width of the rail.
+    pub const fn right_rail(thickness: Size) -> Self {
+        Self::new(
+            Anchor::end(Size::ZERO, thickness),
+            Anchor::stretch(Size::ZERO, Size::ZERO),
+        )
+    }
+
+    /// Square floating action button kept at the bottom-right corner of the parent.
+    /// * `size` - width and height of the button.
+    /// * `inset` - distance from the right and bottom edges.
+    pub fn floating_action_button(size: Size, inset: Size) -> Self {
+        Self::new(
+            Anchor::end(inset.clone(), size.clone()),
+            Anchor::end(inset, size),
+        )
+    }
+
+    // Rect of the node `node_id` placed in `parent_rect`.
+    pub(crate) fn apply(
+        &self,
+        parent_rect: Rect,
+        screen_size: Dimension,
+        scene: &Scene,
+        node_id: NodeId,
+    ) -> Rect {
+        let horizontal_aspect = self.horizontal.size().and_then(|size| size.aspect(scene));
+        let vertical_aspect = self.vertical.size().and_then(|size| size.aspect(scene));
+
+        // An axis sized by Size::FitContent takes the node's content size, or 0 if it has none.
+        let fits = |anchor: &Anchor| matches!(anchor.size(), Some(Size::FitContent));
+        let content = (fits(&self.horizontal) || fits(&self.vertical))
+            .then(|| scene.content_size(node_id, parent_rect.dimension(), screen_size))
+            .flatten()
+            .unwrap_or(Dimension::new(0, 0));
+        let content_width = fits(&self.horizontal).then_some(content.width);
+        let content_height = fits(&self.vertical).then_some(content.height);
+
+        // An axis sized by Size::AspectOf is derived from the other axis, so the other one is resolved first.
+        if let (Some(aspect), None) = (horizontal_aspect, vertical_aspect) {
+            let (y, h) = self.vertical.apply(
+                parent_rect.y,
+                parent_rect.h,
+                parent_rect.dimension(),
+                screen_size,
+                scene.scale_factor,
+                content_height,
+            );
+            let (x, w) = self.horizontal.apply(
+                parent_rect.x,
+                parent_rect.w,
+                parent_rect.dimension(),
+                screen_size,
+                scene.scale_factor,
+                Some(aspect.width_for(h)),
+            );
+            return Rect::new(x, y, w, h);
+        }
+
         let (x, w) = self.horizontal.apply(
             parent_rect.x,
             parent_rect.w,
             parent_rect.dimension(),
             screen_size,
+            scene.scale_factor,
+            content_width,
         );
         let (y, h) = self.vertical.apply(
             parent_rect.y,
             parent_rect.h,
             parent_rect.dimension(),
             screen_size,
+            scene.scale_factor,
+            vertical_aspect
+                .map(|aspect| aspect.height_for(w))
+                .or(content_height),
         );
         Rect::new(x, y, w, h)
     }
+
+    // Position `t` (0~1) of the way from `self` to `to`, or None if their anchors or sizes are of different kinds.
+    // z_index stays until the end, where it switches to the target's.
+    pub(crate) fn lerp(&self, to: &Position, t: f32) -> Option<Position> {
+        let horizontal = self.horizontal.lerp(&to.horizontal, t)?;
+        let vertical = self.vertical.lerp(&to.vertical, t)?;
+        if t >= 1.0 {
+            return Some(to.clone());
+        }
+        Some(Position {
+            horizontal,
+            vertical,
+            z_index: self.z_index,
+        })
+    }
 }
 
 /// Anchor and size information used in [Position].
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Anchor {
     /// Anchor node start at parent start. Start means left for horizontal and top for vertical.
     /// * `pos` - sets node offset from parent start to the end direction.
@@ -102,38 +1453,99 @@ pub enum Anchor {
 }
 
 impl Anchor {
+    // `size_override` replaces the resolved size, for sizes derived from the other axis.
     fn apply(
         &self,
         parent_pos: i32,
         parent_size_curr: i32,
         parent_size: Dimension,
         screen_size: Dimension,
+        scale_factor: f32,
+        size_override: Option<i32>,
     ) -> (i32, i32) {
+        let resolve_size = |size: &Size| {
+            size_override.unwrap_or_else(|| size.resolve(parent_size, screen_size, scale_factor))
+        };
         match self {
             Anchor::Start { pos: start, size } => (
-                parent_pos + start.resolve(parent_size, screen_size),
-                size.resolve(parent_size, screen_size),
+                parent_pos + start.resolve(parent_size, screen_size, scale_factor),
+                resolve_size(size),
             ),
             Anchor::Center { pos, size } => (
                 parent_pos
-                    + pos.resolve(parent_size, screen_size)
-                    + (parent_size_curr - size.resolve(parent_size, screen_size)) / 2,
-                size.resolve(parent_size, screen_size),
+                    + pos.resolve(parent_size, screen_size, scale_factor)
+                    + (parent_size_curr - resolve_size(size)) / 2,
+                resolve_size(size),
             ),
             Anchor::End { pos: end, size } => (
                 parent_pos + parent_size_curr
-                    - end.resolve(parent_size, screen_size)
-                    - size.resolve(parent_size, screen_size),
-                size.resolve(parent_size, screen_size),
+                    - end.resolve(parent_size, screen_size, scale_factor)
+                    - resolve_size(size),
+                resolve_size(size),
             ),
             Anchor::Stretch { start, end } => {
-                let left = parent_pos + start.resolve(parent_size, screen_size);
-                let right = parent_pos + parent_size_curr - end.resolve(parent_size, screen_size);
+                let left = parent_pos + start.resolve(parent_size, screen_size, scale_factor);
+                let right = parent_pos + parent_size_curr
+                    - end.resolve(parent_size, screen_size, scale_factor);
                 (left, right - left)
             }
         }
     }
 
+    // Anchor `t` (0~1) of the way from `self` to `to`, or None if they or their sizes are of different kinds.
+    fn lerp(&self, to: &Anchor, t: f32) -> Option<Anchor> {
+        Some(match (self, to) {
+            (
+                Anchor::Start { pos, size },
+                Anchor::Start {
+                    pos: to_pos,
+                    size: to_size,
+                },
+            ) => Anchor::start(pos.lerp(to_pos, t)?, size.lerp(to_size, t)?),
+            (
+                Anchor::Center { pos, size },
+                Anchor::Center {
+                    pos: to_pos,
+                    size: to_size,
+                },
+            ) => Anchor::center(pos.lerp(to_pos, t)?, size.lerp(to_size, t)?),
+            (
+                Anchor::End { pos, size },
+                Anchor::End {
+                    pos: to_pos,
+                    size: to_size,
+                },
+            ) => Anchor::end(pos.lerp(to_pos, t)?, size.lerp(to_size, t)?),
+            (
+                Anchor::Stretch { start, end },
+                Anchor::Stretch {
+                    start: to_start,
+                    end: to_end,
+                },
+            ) => Anchor::stretch(start.lerp(to_start, t)?, end.lerp(to_end, t)?),
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> Option<&Size> {
+        match self {
+            Anchor::Start { size, .. } | Anchor::Center { size, .. } | Anchor::End { size, .. } => {
+                Some(size)
+            }
+            Anchor::Stretch { .. } => None,
+        }
+    }
+
+    // Replace the offset of a Start/Center/End anchor. Stretch anchors are not changed.
+    fn set_pos(&mut self, new_pos: Size) {
+        match self {
+            Anchor::Start { pos, .. } | Anchor::Center { pos, .. } | Anchor::End { pos, .. } => {
+                *pos = new_pos
+            }
+            Anchor::Stretch { .. } => (),
+        }
+    }
+
     pub const fn start(pos: Size, size: Size) -> Self {
         Self::Start { pos, size }
     }
@@ -153,10 +1565,17 @@ impl Anchor {
 
 /// Physical size such as width and height. Can be absolute pixel or relative to the parent's width or height.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Size {
-    /// Size in pixel. does not change when parent size changes.
+    /// Size in physical pixels of the screen. does not change when parent size changes.
+    /// It does not follow the display scale either, so it looks smaller on HiDPI displays; use [Size::LogicalPixel] for that.
     Pixel(i32),
 
+    /// Size in logical pixels, which are physical pixels divided by the scale factor of the display, see
+    /// [Scene::scale_factor]: physical pixels are logical pixels times the scale factor.
+    /// `LogicalPixel(100.0)` is 100 pixels at scale 1 and 200 pixels at scale 2.
+    LogicalPixel(f32),
+
     /// Size relative to the parent node's width.
     /// the value sets ratio to the parent width.
     ParentWidth(f32),
@@ -176,19 +1595,429 @@ pub enum Size {
     /// The size will be determined by weighted division among the 'Size::Weight' nodes over the available size left.
     /// Can only be used in Row/Column node.
     Weight(f32),
+
+    /// Size derived from the node's other axis so that the texture keeps its aspect ratio, times the multiplier.
+    /// Used as a width, it is the height times the texture's width/height; used as a height, the other way around.
+    /// In Row/Column it is derived from the cross axis of the row/column.
+    /// Resolves to 0 when the texture's size is unknown (not registered, or not a decodable image),
+    /// and when both axes of a [Position] use it.
+    AspectOf(TextureId, f32),
+
+    /// `base` kept between `min` and `max`, all resolved against the same parent and screen.
+    /// If `min` resolves larger than `max`, `max` wins.
+    /// Weight, AspectOf and FitContent resolve to 0 inside it.
+    Clamp {
+        base: Box<Size>,
+        min: Box<Size>,
+        max: Box<Size>,
+    },
+
+    /// Size of the node's content along the axis: the measured text of a Text node, the shown region of a Texture node
+    /// in texture pixels, or the content of a Padding node's child plus the insets.
+    /// The text size and insets resolve against the parent's size here, so prefer [Size::Pixel] or
+    /// [Size::LogicalPixel] for them, as relative ones resolve differently once the node is laid out.
+    /// Resolves to 0 for nodes without content size, like Rect and container nodes, for hidden nodes, and for Text nodes
+    /// while no font is set.
+    FitContent,
+
+    /// Sum of the sizes, each resolved against the same parent and screen,
+    /// e.g. `Sum(vec![ParentWidth(0.5), Pixel(20)])` is half the parent width plus 20 pixels.
+    /// Weight, AspectOf and FitContent resolve to 0 inside it.
+    Sum(Vec<Size>),
+}
+
+/// Placement of Row/Column children across the stacking direction: horizontal in a Row, vertical in a Column.
+/// Children with an intrinsic aspect ratio, Texture nodes whose texture size is known, keep it: their cross size is
+/// derived from the size along the stacking direction. Other children have no intrinsic cross size, so they fill
+/// the cross axis whatever the alignment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CrossAlign {
+    /// Left in a Row, top in a Column.
+    Start,
+    Center,
+    /// Right in a Row, bottom in a Column.
+    End,
+    /// Fill the cross axis, stretching children with an intrinsic aspect ratio.
+    #[default]
+    Stretch,
+}
+
+impl CrossAlign {
+    // Start and size along the cross axis of a child in `available` pixels from `start`, whose intrinsic cross size
+    // is `size` if it has one.
+    pub(crate) fn place(self, start: i32, available: i32, size: Option<i32>) -> (i32, i32) {
+        match (self, size) {
+            (CrossAlign::Stretch, _) | (_, None) => (start, available),
+            (CrossAlign::Start, Some(size)) => (start, size),
+            (CrossAlign::Center, Some(size)) => (start + (available - size) / 2, size),
+            (CrossAlign::End, Some(size)) => (start + available - size, size),
+        }
+    }
+}
+
+/// How the pixels of a node combine with what is drawn behind them, set with [Scene::set_blend_mode].
+/// `src` is the node's color and `dst` the color behind it, both linear RGB.
+/// Nodes of every mode but Replace are drawn after the opaque nodes, back to front, and do not hide what is
+/// drawn after them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendMode {
+    /// Opaque pixels replace what is behind them. Translucent colors, anti-aliased edges and glyphs are still
+    /// alpha blended, so that they look right.
+    #[default]
+    Replace,
+    /// `src * src_alpha + dst * (1 - src_alpha)` for every pixel, opaque or not.
+    Alpha,
+    /// `dst + src * src_alpha`. Brightens what is behind, for glows and particles.
+    Additive,
+    /// `dst * mix(1, src, src_alpha)`. Darkens what is behind, for shadows and tints. White changes nothing.
+    Multiply,
+}
+
+/// Width over height of a texture, times the multiplier of [Size::AspectOf].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Aspect(f32);
+
+impl Aspect {
+    pub fn width_for(self, height: i32) -> i32 {
+        (height as f32 * self.0) as i32
+    }
+
+    pub fn height_for(self, width: i32) -> i32 {
+        if self.0 > 0.0 {
+            (width as f32 / self.0) as i32
+        } else {
+            0
+        }
+    }
 }
 
 impl Size {
     pub const ZERO: Self = Self::Pixel(0);
 
-    pub(crate) fn resolve(&self, parent_size: Dimension, screen_size: Dimension) -> i32 {
+    /// Shorthand for [Size::Clamp].
+    pub fn clamp(base: Size, min: Size, max: Size) -> Self {
+        Self::Clamp {
+            base: Box::new(base),
+            min: Box::new(min),
+            max: Box::new(max),
+        }
+    }
+
+    pub(crate) fn resolve(
+        &self,
+        parent_size: Dimension,
+        screen_size: Dimension,
+        scale_factor: f32,
+    ) -> i32 {
         match self {
             Size::Pixel(pixel) => *pixel,
+            Size::LogicalPixel(pixel) => (pixel * scale_factor).round() as i32,
             Size::ParentWidth(ratio) => (parent_size.width as f32 * ratio) as i32,
             Size::ParentHeight(ratio) => (parent_size.height as f32 * ratio) as i32,
             Size::ScreenWidth(ratio) => (screen_size.width as f32 * ratio) as i32,
             Size::ScreenHeight(ratio) => (screen_size.height as f32 * ratio) as i32,
-            Size::Weight(_) => 0,
+            Size::Weight(_) | Size::AspectOf(..) | Size::FitContent => 0,
+            Size::Clamp { base, min, max } => base
+                .resolve(parent_size, screen_size, scale_factor)
+                .max(min.resolve(parent_size, screen_size, scale_factor))
+                .min(max.resolve(parent_size, screen_size, scale_factor)),
+            Size::Sum(sizes) => sizes
+                .iter()
+                .map(|size| size.resolve(parent_size, screen_size, scale_factor))
+                .sum(),
+        }
+    }
+
+    // Size `t` (0~1) of the way from `self` to `to`, or None if they are different variants, AspectOf of different
+    // textures or Sum of different lengths. Pixel sizes are rounded.
+    fn lerp(&self, to: &Size, t: f32) -> Option<Size> {
+        let mix = |from: f32, to: f32| from + (to - from) * t;
+        Some(match (self, to) {
+            (Size::Pixel(from), Size::Pixel(to)) => {
+                Size::Pixel(mix(*from as f32, *to as f32).round() as i32)
+            }
+            (Size::LogicalPixel(from), Size::LogicalPixel(to)) => {
+                Size::LogicalPixel(mix(*from, *to))
+            }
+            (Size::ParentWidth(from), Size::ParentWidth(to)) => Size::ParentWidth(mix(*from, *to)),
+            (Size::ParentHeight(from), Size::ParentHeight(to)) => {
+                Size::ParentHeight(mix(*from, *to))
+            }
+            (Size::ScreenWidth(from), Size::ScreenWidth(to)) => Size::ScreenWidth(mix(*from, *to)),
+            (Size::ScreenHeight(from), Size::ScreenHeight(to)) => {
+                Size::ScreenHeight(mix(*from, *to))
+            }
+            (Size::Weight(from), Size::Weight(to)) => Size::Weight(mix(*from, *to)),
+            (Size::AspectOf(texture_id, from), Size::AspectOf(to_texture_id, to))
+                if texture_id == to_texture_id =>
+            {
+                Size::AspectOf(*texture_id, mix(*from, *to))
+            }
+            (
+                Size::Clamp { base, min, max },
+                Size::Clamp {
+                    base: to_base,
+                    min: to_min,
+                    max: to_max,
+                },
+            ) => Size::clamp(
+                base.lerp(to_base, t)?,
+                min.lerp(to_min, t)?,
+                max.lerp(to_max, t)?,
+            ),
+            (Size::FitContent, Size::FitContent) => Size::FitContent,
+            (Size::Sum(from), Size::Sum(to)) if from.len() == to.len() => Size::Sum(
+                from.iter()
+                    .zip(to)
+                    .map(|(from, to)| from.lerp(to, t))
+                    .collect::<Option<_>>()?,
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Aspect ratio of a [Size::AspectOf] whose texture size is known.
+    pub(crate) fn aspect(&self, scene: &Scene) -> Option<Aspect> {
+        let Size::AspectOf(texture_id, multiplier) = self else {
+            return None;
+        };
+        let (width, height) = scene.texture_size(*texture_id)?;
+        Some(Aspect(width as f32 / height as f32 * multiplier))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn sum_adds_sizes_resolved_against_the_same_parent() {
+        let size = Size::Sum(vec![Size::ParentWidth(0.5), Size::Pixel(20)]);
+        let parent = Dimension::new(400, 300);
+        assert_eq!(size.resolve(parent, Dimension::new(800, 600), 1.0), 220);
+    }
+
+    #[test]
+    fn sticky_positions_stay_on_their_edge_at_any_screen_size() {
+        let scene = Scene::default();
+        for (width, height) in [(800, 600), (1920, 1080), (320, 480)] {
+            let screen = Dimension::new(width, height);
+            let place = |position: Position| {
+                position.apply(Rect::new(0, 0, width, height), screen, &scene, 0)
+            };
+            assert_eq!(
+                place(Position::top_bar(Size::Pixel(40))),
+                Rect::new(0, 0, width, 40)
+            );
+            assert_eq!(
+                place(Position::bottom_bar(Size::Pixel(40))),
+                Rect::new(0, height - 40, width, 40)
+            );
+            assert_eq!(
+                place(Position::left_rail(Size::Pixel(60))),
+                Rect::new(0, 0, 60, height)
+            );
+            assert_eq!(
+                place(Position::right_rail(Size::Pixel(60))),
+                Rect::new(width - 60, 0, 60, height)
+            );
+            assert_eq!(
+                place(Position::floating_action_button(
+                    Size::Pixel(56),
+                    Size::Pixel(16)
+                )),
+                Rect::new(width - 72, height - 72, 56, 56)
+            );
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn scene_lowers_the_same_after_a_json_round_trip() {
+        let mut guiug = crate::Guiug::default();
+        let panel = guiug.bordered_rect_node(
+            Vec4::new(0.2, 0.3, 0.4, 1.0),
+            Vec4::ONE,
+            Size::LogicalPixel(2.0),
+        );
+        let dot = guiug.rect_node(Vec4::new(1.0, 0.0, 0.0, 0.5));
+        let sprite = guiug.sprite_node(3, Vec2::new(0.0, 0.5), Vec2::new(0.5, 1.0));
+        let root = guiug.layer_node(vec![
+            (Position::fill_with_margin(Size::ParentWidth(0.1)), panel),
+            (Position::centered(Size::Pixel(20)).with_z_index(2), dot),
+            (
+                Position::floating_action_button(Size::Pixel(32), Size::Pixel(8)),
+                sprite,
+            ),
+        ]);
+        guiug.set_root(root).unwrap();
+        let scene = guiug.scene_mut();
+        scene.set_rotation(dot, 0.5);
+        scene.set_texture_flip(sprite, true, false).unwrap();
+        scene.set_color_space(ColorSpace::Srgb);
+
+        let json = serde_json::to_string(scene).unwrap();
+        let loaded: Scene = serde_json::from_str(&json).unwrap();
+        let screen = Dimension::new(320, 240);
+        assert_eq!(
+            crate::draw::lower(&loaded, screen),
+            crate::draw::lower(scene, screen)
+        );
+    }
+
+    #[test]
+    fn starting_an_animation_leaves_the_scene_clean_until_it_moves() {
+        let mut guiug = crate::Guiug::default();
+        let rect = guiug.rect_node(Vec4::ONE);
+        let root = guiug.layer_node(vec![(Position::centered(Size::Pixel(10)), rect)]);
+        guiug.set_root(root).unwrap();
+        let scene = guiug.scene_mut();
+        scene.dirty = false;
+
+        let easing = Easing::Linear;
+        assert!(scene.animate_color(root, Vec4::ZERO, 1.0, easing).is_err());
+        assert!(
+            scene
+                .animate_position(root, Position::centered(Size::Pixel(20)), 1.0, easing)
+                .is_err()
+        );
+        scene.animate_color(rect, Vec4::ZERO, 1.0, easing).unwrap();
+        scene
+            .animate_position(rect, Position::centered(Size::Pixel(20)), 1.0, easing)
+            .unwrap();
+        assert!(!scene.dirty);
+
+        scene.advance_animations(0.5);
+        assert!(scene.dirty);
+        assert_eq!(scene.color(rect), Some(&Vec4::splat(0.5)));
+    }
+
+    // Average time of `run` over `iterations` runs, after one warm-up run.
+    pub(crate) fn time<T>(iterations: u32, mut run: impl FnMut() -> T) -> std::time::Duration {
+        std::hint::black_box(run());
+        let start = std::time::Instant::now();
+        for _ in 0..iterations {
+            std::hint::black_box(run());
+        }
+        start.elapsed() / iterations
+    }
+
+    // Timing harness rather than a check, run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_building_nodes_with_capacity() {
+        const NODES: usize = 5000;
+        let build = |mut guiug: crate::Guiug| {
+            let rects = (0..NODES - 1)
+                .map(|_| (Position::FULL, guiug.rect_node(Vec4::ONE)))
+                .collect();
+            guiug.layer_node(rects);
+            guiug.scene_mut().node_count()
+        };
+        let default = time(200, || build(crate::Guiug::default()));
+        let with_capacity = time(200, || build(crate::Guiug::with_capacity(NODES)));
+        println!("{NODES} nodes: Default {default:?}, with_capacity {with_capacity:?}");
+    }
+
+    #[test]
+    fn fit_content_takes_the_padded_texture_size() {
+        let mut guiug = crate::Guiug::default();
+        let texture_id = guiug
+            .scene_mut()
+            .add_texture_rgba8(30, 20, vec![255; 30 * 20 * 4])
+            .unwrap();
+        let insets = || {
+            [
+                Size::Pixel(4),
+                Size::Pixel(2),
+                Size::Pixel(6),
+                Size::Pixel(3),
+            ]
+        };
+        let texture = guiug.texture_node(texture_id);
+        let padded = guiug.padding_node(insets(), texture);
+        let rect = guiug.rect_node(Vec4::ONE);
+        let text = guiug.text_node("no font", Size::Pixel(16), Vec4::ONE);
+        let column_texture = guiug.texture_node(texture_id);
+        let column_padded = guiug.padding_node(insets(), column_texture);
+        let filler = guiug.rect_node(Vec4::ONE);
+        let column = guiug.column_node(
+            vec![
+                (Size::FitContent, column_padded),
+                (Size::Weight(1.0), filler),
+            ],
+            Size::ZERO,
+            crate::CrossAlign::Stretch,
+        );
+        let fit = |horizontal: Anchor| {
+            Position::new(horizontal, Anchor::start(Size::ZERO, Size::FitContent))
+        };
+        let root = guiug.layer_node(vec![
+            (fit(Anchor::start(Size::ZERO, Size::FitContent)), padded),
+            (fit(Anchor::end(Size::ZERO, Size::FitContent)), rect),
+            (fit(Anchor::center(Size::ZERO, Size::FitContent)), text),
+            (Position::bottom_bar(Size::Pixel(50)), column),
+        ]);
+        guiug.set_root(root).unwrap();
+
+        let scene = guiug.scene_mut();
+        let screen = Dimension::new(200, 100);
+        let layout = |node_id| scene.layout_rect(node_id, screen).unwrap();
+        // the insets add 10 pixels to the width and 5 to the height
+        assert_eq!(layout(padded), Rect::new(0, 0, 40, 25).into());
+        assert_eq!(layout(texture), Rect::new(4, 2, 30, 20).into());
+        // a Rect has no content, nor a Text without a font
+        assert_eq!(layout(rect), Rect::new(200, 0, 0, 0).into());
+        assert_eq!(layout(text), Rect::new(100, 0, 0, 0).into());
+        assert_eq!(layout(column_padded), Rect::new(0, 50, 40, 50).into());
+        assert_eq!(layout(filler), Rect::new(40, 50, 160, 50).into());
+    }
+
+    #[test]
+    fn cyclic_layer_reference_is_rejected_and_lowering_terminates() {
+        let mut guiug = crate::Guiug::default();
+        let inner = guiug.layer_node(vec![]);
+        let outer = guiug.layer_node(vec![(Position::fill_with_margin(Size::ZERO), inner)]);
+        let scene = guiug.scene_mut();
+        assert!(matches!(
+            scene.layer_insert(inner, Position::fill_with_margin(Size::ZERO), outer),
+            Err(SceneError::WouldCycle { .. })
+        ));
+        assert!(matches!(
+            scene.reparent(outer, inner, Position::fill_with_margin(Size::ZERO)),
+            Err(SceneError::WouldCycle { .. })
+        ));
+        assert!(scene.children_of(inner).is_empty());
+
+        // layer_node does not check its children, so it can refer to the layer created next
+        let dot = guiug.rect_node(Vec4::ONE);
+        let first = guiug.layer_node(vec![(Position::fill_with_margin(Size::ZERO), dot + 2)]);
+        let second = guiug.layer_node(vec![
+            (Position::fill_with_margin(Size::ZERO), first),
+            (Position::centered(Size::Pixel(10)), dot),
+        ]);
+        assert_eq!(second, dot + 2);
+        guiug.set_root(first).unwrap();
+        let scene = guiug.scene_mut();
+        assert!(matches!(
+            scene.validate(),
+            Err(SceneError::Cycle { node_id }) if node_id == first
+        ));
+
+        // the repeated layer is skipped, and the rest of the cycle is still laid out and drawn
+        let screen = Dimension::new(100, 100);
+        assert_eq!(crate::draw::lower(scene, screen).commands.len(), 1);
+        assert_eq!(
+            scene.layout_rect(first, screen),
+            Some(Rect::new(0, 0, 100, 100).into())
+        );
+        assert_eq!(
+            scene.layout_rect(dot, screen),
+            Some(Rect::new(45, 45, 10, 10).into())
+        );
+        assert_eq!(scene.node_at((50, 50), screen), Some(dot));
+        assert_eq!(scene.node_at((5, 5), screen), None);
+    }
 }
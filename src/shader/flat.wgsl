@@ -4,11 +4,39 @@ struct VertexInput {
     @location(2) instance_position: vec3i,
     @location(3) instance_scale: vec2i,
     @location(4) instance_color: vec4f,
+    @location(5) instance_radius: f32,
+    @location(6) instance_shape: u32,
+    @location(7) instance_border_width: f32,
+    @location(8) instance_border_color: vec4f,
+    @location(9) instance_end_color: vec4f,
+    @location(10) instance_gradient: vec2f,
+    @location(11) instance_matrix: vec4f,
+    @location(12) instance_translation: vec2f,
 }
 
+const SHAPE_RECT: u32 = 0u;
+const SHAPE_ELLIPSE: u32 = 1u;
+// rect grown by the blur radius, stored in the corner radius, fading out across twice the radius
+const SHAPE_SHADOW: u32 = 2u;
+// line segment along x, only its long edges are anti-aliased
+const SHAPE_SEGMENT: u32 = 3u;
+// top-left half of the rect cut along its diagonal, only the diagonal is anti-aliased
+const SHAPE_BEVEL: u32 = 4u;
+// checkerboard of squares the size of the corner radius, the top-left one filled
+const SHAPE_CHECKERBOARD: u32 = 5u;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) color: vec4f,
+    // position inside the rect in pixel, from the top-left corner
+    @location(1) local: vec2f,
+    @location(2) @interpolate(flat) size: vec2f,
+    @location(3) @interpolate(flat) radius: f32,
+    @location(4) @interpolate(flat) shape: u32,
+    @location(5) @interpolate(flat) border_width: f32,
+    @location(6) border_color: vec4f,
+    @location(7) end_color: vec4f,
+    @location(8) @interpolate(flat) gradient: vec2f,
 }
 
 @group(0) @binding(0)
@@ -20,24 +48,107 @@ fn vs_main(
     in: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    let position_pixel = in.position * vec2f(in.instance_scale) + vec2f(f32(in.instance_position.x), f32(i32(screen_size.y) - in.instance_position.y));
+    // layout position in pixel, y down. The vertex position goes down from 0 to -1.
+    let position_layout = vec2f(in.instance_position.xy) + vec2f(in.position.x, -in.position.y) * vec2f(in.instance_scale);
+    // rotation of the node and its ancestors
+    let transform = mat2x2f(in.instance_matrix.xy, in.instance_matrix.zw);
+    let position_pixel = transform * position_layout + in.instance_translation;
 
     let screen_size_f = vec2f(screen_size.xy);
 
     let position_normalized = position_pixel / screen_size_f;
-    let position_ndc = vec4f(2.0 * vec2f(position_normalized) - 1.0, f32(in.instance_position.z) / f32(screen_size.z), 1.0);
+    let position_ndc = vec4f(2.0 * position_normalized.x - 1.0, 1.0 - 2.0 * position_normalized.y, f32(in.instance_position.z) / f32(screen_size.z), 1.0);
 
 
 
     out.clip_position = position_ndc;
     out.color = in.instance_color;
+    out.local = in.uv * vec2f(in.instance_scale);
+    out.size = vec2f(in.instance_scale);
+    out.radius = in.instance_radius;
+    out.shape = in.instance_shape;
+    out.border_width = in.instance_border_width;
+    out.border_color = in.instance_border_color;
+    out.end_color = in.instance_end_color;
+    out.gradient = in.instance_gradient;
 
     return out;
 }
 
+// Signed distance from the edge of the rounded rect, negative inside.
+fn rounded_rect_distance(local: vec2f, size: vec2f, radius: f32) -> f32 {
+    let half = size * 0.5;
+    let q = abs(local - half) - half + radius;
+    return length(max(q, vec2f(0.0))) + min(max(q.x, q.y), 0.0) - radius;
+}
+
+// Approximate signed distance from the edge of the inscribed ellipse, negative inside.
+// The implicit function is divided by its gradient length, which is exact near the edge.
+fn ellipse_distance(local: vec2f, size: vec2f) -> f32 {
+    let half = max(size * 0.5, vec2f(1e-4));
+    let p = (local - half) / half;
+    let gradient = 2.0 * p / half;
+    return (dot(p, p) - 1.0) / max(length(gradient), 1e-4);
+}
+
+fn shade(in: VertexOutput) -> vec4f {
+    // derivatives need uniform control flow, so the diagonal of bevels is measured for every shape
+    let uv = in.local / max(in.size, vec2f(1.0));
+    let diagonal = uv.x + uv.y - 1.0;
+    let diagonal_width = max(fwidth(diagonal), 1e-4);
+
+    var distance: f32;
+    var coverage: f32;
+    if in.shape == SHAPE_SEGMENT {
+        distance = abs(in.local.y - in.size.y * 0.5) - in.size.y * 0.5;
+        coverage = clamp(0.5 - distance, 0.0, 1.0);
+    } else if in.shape == SHAPE_BEVEL {
+        distance = diagonal / diagonal_width;
+        coverage = clamp(0.5 - distance, 0.0, 1.0);
+    } else if in.shape == SHAPE_CHECKERBOARD {
+        let cell = floor(in.local / in.radius);
+        distance = -1.0;
+        coverage = select(0.0, 1.0, (i32(cell.x) + i32(cell.y)) % 2 == 0);
+    } else if in.shape == SHAPE_SHADOW {
+        // distance from the edge of the shadowed rect, in the middle of the fade
+        let blur = max(in.radius, 0.5);
+        distance = rounded_rect_distance(in.local - in.radius, in.size - 2.0 * in.radius, 0.0);
+        coverage = 1.0 - smoothstep(-blur, blur, distance);
+    } else {
+        if in.shape == SHAPE_ELLIPSE {
+            distance = ellipse_distance(in.local, in.size);
+        } else {
+            distance = rounded_rect_distance(in.local, in.size, in.radius);
+        }
+        // pixels crossed by the edge are partially covered
+        coverage = clamp(0.5 - distance, 0.0, 1.0);
+    }
+    // linear gradient centered on the rect, from color at uv 0.5 - gradient / 2 to end_color at 0.5 + gradient / 2
+    var color = in.color;
+    if any(in.gradient != vec2f(0.0)) {
+        let t = dot(uv - 0.5, in.gradient) / dot(in.gradient, in.gradient) + 0.5;
+        color = mix(in.color, in.end_color, clamp(t, 0.0, 1.0));
+    }
+    // the border covers pixels closer to the edge than its width, blending into the fill across its inner edge
+    if in.border_width > 0.0 {
+        color = mix(color, in.border_color, clamp(distance + in.border_width + 0.5, 0.0, 1.0));
+    }
+    return vec4f(color.rgb, color.a * coverage);
+}
+
 @fragment
 fn fs_main(
     in: VertexOutput,
 ) -> @location(0) vec4f {
-    return in.color;
-}
\ No newline at end of file
+    return shade(in);
+}
+
+// For the multiply blend, which multiplies the target by the output color: transparent pixels become white,
+// so that they leave the target unchanged.
+@fragment
+fn fs_multiply(
+    in: VertexOutput,
+) -> @location(0) vec4f {
+    let color = shade(in);
+    return vec4f(mix(vec3f(1.0), color.rgb, color.a), color.a);
+}
@@ -3,11 +3,19 @@ struct VertexInput {
     @location(1) uv: vec2f,
     @location(2) instance_position: vec3i,
     @location(3) instance_scale: vec2i,
+    @location(4) instance_tint: vec4f,
+    @location(5) instance_uv_offset: vec2f,
+    @location(6) instance_uv_extent: vec2f,
+    @location(7) instance_matrix: vec4f,
+    @location(8) instance_translation: vec2f,
+    @location(9) instance_layer: u32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) uv: vec2f,
+    @location(1) @interpolate(flat) tint: vec4f,
+    @location(2) @interpolate(flat) layer: u32,
 }
 
 @group(0) @binding(0)
@@ -18,15 +26,21 @@ fn vs_main(
     in: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    let position_pixel = in.position * vec2f(in.instance_scale) + vec2f(f32(in.instance_position.x), f32(i32(screen_size.y) - in.instance_position.y));
+    // layout position in pixel, y down. The vertex position goes down from 0 to -1.
+    let position_layout = vec2f(in.instance_position.xy) + vec2f(in.position.x, -in.position.y) * vec2f(in.instance_scale);
+    // rotation of the node and its ancestors
+    let transform = mat2x2f(in.instance_matrix.xy, in.instance_matrix.zw);
+    let position_pixel = transform * position_layout + in.instance_translation;
 
     let screen_size_f = vec2f(screen_size.xy);
 
     let position_normalized = position_pixel / screen_size_f;
-    let position_ndc = vec4f(2.0 * vec2f(position_normalized) - 1.0, f32(in.instance_position.z) / f32(screen_size.z), 1.0);
+    let position_ndc = vec4f(2.0 * position_normalized.x - 1.0, 1.0 - 2.0 * position_normalized.y, f32(in.instance_position.z) / f32(screen_size.z), 1.0);
 
     out.clip_position = position_ndc;
-    out.uv = in.uv;
+    out.uv = in.instance_uv_offset + in.uv * in.instance_uv_extent;
+    out.tint = in.instance_tint;
+    out.layer = in.instance_layer;
 
     return out;
 }
@@ -40,5 +54,26 @@ var fsampler: sampler;
 fn fs_main(
     in: VertexOutput,
 ) -> @location(0) vec4f {
-    return textureSample(ftexture, fsampler, in.uv);
+    return textureSample(ftexture, fsampler, in.uv) * in.tint;
+}
+
+// For the multiply blend, which multiplies the target by the output color: transparent texels become white,
+// so that they leave the target unchanged.
+@fragment
+fn fs_multiply(
+    in: VertexOutput,
+) -> @location(0) vec4f {
+    let color = textureSample(ftexture, fsampler, in.uv) * in.tint;
+    return vec4f(mix(vec3f(1.0), color.rgb, color.a), color.a);
+}
+
+// Same texture slot bound to an array of equally sized textures, indexed per instance.
+@group(1) @binding(0)
+var ftexture_array: texture_2d_array<f32>;
+
+@fragment
+fn fs_array(
+    in: VertexOutput,
+) -> @location(0) vec4f {
+    return textureSample(ftexture_array, fsampler, in.uv, in.layer) * in.tint;
 }
\ No newline at end of file
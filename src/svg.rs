@@ -0,0 +1,315 @@
+//! SVG export of the laid out scene.
+//! Shader effects are not reproduced, and opacity groups fade each of their nodes instead of the group as a whole.
+//! Shadows are stacked translucent rects.
+
+use std::{borrow::Cow, fmt::Write};
+
+use glam::{Vec2, Vec4};
+
+use crate::{
+    draw::{self, DrawCommand},
+    scene::Scene,
+    texture::{self, TextureInfo, TextureInfoManager},
+    types::{Dimension, Rect},
+};
+
+pub(crate) fn render(
+    scene: &Scene,
+    texture_infos: Option<&TextureInfoManager>,
+    screen_size: Dimension,
+) -> String {
+    let commands = draw::lower(scene, screen_size).flatten();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        screen_size.width, screen_size.height
+    );
+
+    // one clipPath per distinct clip rect
+    let mut clips: Vec<Rect> = Vec::new();
+    for command in &commands {
+        if let DrawCommand::Clip(Some(clip)) = command
+            && !clips.contains(clip)
+        {
+            clips.push(*clip);
+        }
+    }
+    if !clips.is_empty() {
+        svg.push_str("<defs>\n");
+        for (num, clip) in clips.iter().enumerate() {
+            let _ = writeln!(
+                svg,
+                r#"<clipPath id="clip{num}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                clip.x, clip.y, clip.w, clip.h
+            );
+        }
+        svg.push_str("</defs>\n");
+    }
+
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{}"/>"#,
+        css_color(scene.background()),
+        scene.background().w.clamp(0.0, 1.0),
+    );
+
+    // clip-path and transform of the following elements
+    let mut clip_attribute = String::new();
+    let mut transform_attribute = String::new();
+    // a clip-path is in the user space of its element, so transformed elements are clipped by a group
+    let mut clip_group = false;
+    let mut attributes = String::new();
+    let mut gradients = 0;
+    for command in &commands {
+        match command {
+            // blend modes are not reproduced, and groups are flattened
+            DrawCommand::Blend(_) | DrawCommand::Group { .. } => (),
+            DrawCommand::Clip(_) | DrawCommand::Transform(_) => {
+                match command {
+                    DrawCommand::Clip(Some(clip)) => {
+                        let num = clips.iter().position(|c| c == clip).unwrap_or_default();
+                        clip_attribute = format!(r#" clip-path="url(#clip{num})""#);
+                    }
+                    DrawCommand::Transform(Some(transform)) => {
+                        let [a, b, c, d] = transform.matrix2.to_cols_array();
+                        let [e, f] = transform.translation.to_array();
+                        transform_attribute =
+                            format!(r#" transform="matrix({a} {b} {c} {d} {e} {f})""#);
+                    }
+                    DrawCommand::Clip(None) => clip_attribute.clear(),
+                    _ => transform_attribute.clear(),
+                }
+                if clip_group {
+                    svg.push_str("</g>\n");
+                    clip_group = false;
+                }
+                if !clip_attribute.is_empty() && !transform_attribute.is_empty() {
+                    let _ = writeln!(svg, "<g{clip_attribute}>");
+                    clip_group = true;
+                    attributes.clone_from(&transform_attribute);
+                } else {
+                    attributes = format!("{clip_attribute}{transform_attribute}");
+                }
+            }
+            DrawCommand::FilledRect {
+                rect,
+                color,
+                radius,
+                border,
+                ..
+            } => {
+                let (fill_rect, fill_radius) = match border {
+                    Some(border) => border.inner(*rect, *radius),
+                    None => (*rect, *radius),
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{fill_radius}" fill="{}" fill-opacity="{}"{attributes}/>"#,
+                    fill_rect.x,
+                    fill_rect.y,
+                    fill_rect.w.max(0),
+                    fill_rect.h.max(0),
+                    css_color(*color),
+                    color.w.clamp(0.0, 1.0),
+                );
+                // SVG strokes are centered on the outline, so the outline is inset by half the width
+                if let Some(border) = border {
+                    let half = border.width as f32 / 2.0;
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{}"{attributes}/>"#,
+                        rect.x as f32 + half,
+                        rect.y as f32 + half,
+                        (rect.w - border.width).max(0),
+                        (rect.h - border.width).max(0),
+                        (*radius as f32 - half).max(0.0),
+                        css_color(border.color),
+                        border.color.w.clamp(0.0, 1.0),
+                        border.width,
+                    );
+                }
+            }
+            DrawCommand::GradientRect {
+                rect,
+                start,
+                end,
+                angle,
+                ..
+            } => {
+                // in bounding box units, like the renderer's gradient in the unit square of the rect
+                let vector = draw::gradient_vector(*angle);
+                let _ = writeln!(
+                    svg,
+                    r#"<linearGradient id="gradient{gradients}" x1="{}" y1="{}" x2="{}" y2="{}"><stop offset="0" stop-color="{}" stop-opacity="{}"/><stop offset="1" stop-color="{}" stop-opacity="{}"/></linearGradient>"#,
+                    0.5 - vector.x / 2.0,
+                    0.5 - vector.y / 2.0,
+                    0.5 + vector.x / 2.0,
+                    0.5 + vector.y / 2.0,
+                    css_color(*start),
+                    start.w.clamp(0.0, 1.0),
+                    css_color(*end),
+                    end.w.clamp(0.0, 1.0),
+                );
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="url(#gradient{gradients})"{attributes}/>"#,
+                    rect.x,
+                    rect.y,
+                    rect.w.max(0),
+                    rect.h.max(0),
+                );
+                gradients += 1;
+            }
+            DrawCommand::Polyline {
+                points,
+                width,
+                color,
+                ..
+            } => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|point| format!("{},{}", point.x, point.y))
+                    .collect();
+                let _ = writeln!(
+                    svg,
+                    r#"<polyline points="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{width}" stroke-linejoin="bevel"{attributes}/>"#,
+                    points.join(" "),
+                    css_color(*color),
+                    color.w.clamp(0.0, 1.0),
+                );
+            }
+            DrawCommand::Checkerboard {
+                rect, color, cell, ..
+            } => {
+                for cell in draw::checkerboard_cells(*rect, *cell) {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{}"{attributes}/>"#,
+                        cell.x,
+                        cell.y,
+                        cell.w,
+                        cell.h,
+                        css_color(*color),
+                        color.w.clamp(0.0, 1.0),
+                    );
+                }
+            }
+            DrawCommand::Shadow {
+                rect, color, blur, ..
+            } => {
+                for (layer, color) in draw::shadow_layers(*rect, *blur, *color) {
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{}"{attributes}/>"#,
+                        layer.x,
+                        layer.y,
+                        layer.w,
+                        layer.h,
+                        css_color(color),
+                        color.w,
+                    );
+                }
+            }
+            DrawCommand::FilledEllipse { rect, color, .. } => {
+                let _ = writeln!(
+                    svg,
+                    r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" fill="{}" fill-opacity="{}"{attributes}/>"#,
+                    rect.x as f32 + rect.w as f32 / 2.0,
+                    rect.y as f32 + rect.h as f32 / 2.0,
+                    rect.w.max(0) as f32 / 2.0,
+                    rect.h.max(0) as f32 / 2.0,
+                    css_color(*color),
+                    color.w.clamp(0.0, 1.0),
+                );
+            }
+            DrawCommand::TexturedQuad {
+                rect,
+                texture_id,
+                tint,
+                uv_min,
+                uv_max,
+                ..
+            } => {
+                let Some(texture_info) = texture::lookup(scene, texture_infos, *texture_id) else {
+                    continue;
+                };
+                // raw, tinted and cropped pixels are embedded as PNG
+                let whole = *uv_min == Vec2::ZERO && *uv_max == Vec2::ONE;
+                let data = match texture_info.encoded_data() {
+                    Some(data) if *tint == Vec4::ONE && whole => Cow::Borrowed(data),
+                    _ => match encode_png(texture_info, *tint, *uv_min, *uv_max) {
+                        Some(data) => Cow::Owned(data),
+                        None => continue,
+                    },
+                };
+                let mime = image::guess_format(&data)
+                    .map(|format| format.to_mime_type())
+                    .unwrap_or("application/octet-stream");
+                let _ = writeln!(
+                    svg,
+                    r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" href="data:{mime};base64,{}"{attributes}/>"#,
+                    rect.x,
+                    rect.y,
+                    rect.w.max(0),
+                    rect.h.max(0),
+                    base64(&data),
+                );
+            }
+        }
+    }
+
+    if clip_group {
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Colors are linear, and the sRGB surface encodes them on write. SVG colors are sRGB-encoded.
+fn css_color(color: Vec4) -> String {
+    let encode = |c: f32| (draw::srgb_encode(c) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        encode(color.x),
+        encode(color.y),
+        encode(color.z)
+    )
+}
+
+fn encode_png(
+    texture_info: &TextureInfo,
+    tint: Vec4,
+    uv_min: Vec2,
+    uv_max: Vec2,
+) -> Option<Vec<u8>> {
+    let mut image = draw::crop_uv(texture_info.to_rgba8().ok()?, uv_min, uv_max);
+    draw::tint_rgba8(&mut image, tint);
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,241 @@
+//! Text layout with a glyph atlas. Glyphs are rasterized once into a texture owned by the scene,
+//! and every glyph of a text node is drawn as a textured quad sampling its atlas cell.
+
+use std::collections::HashMap;
+
+use ab_glyph::{Font as _, FontVec, PxScale, ScaleFont};
+use glam::Vec2;
+
+use crate::{texture::TextureId, types::Rect};
+
+/// Line height in pixels glyphs are rasterized at. Text of other sizes scales the glyphs,
+/// sampling the atlas mipmaps when smaller.
+const RASTER_SIZE: f32 = 48.0;
+
+/// Width and height of the atlas texture in pixels.
+pub(crate) const ATLAS_SIZE: u32 = 1024;
+
+// empty pixels around each glyph, so that the mipmaps of neighboring glyphs do not bleed into each other
+const PADDING: u32 = 4;
+
+/// Failure to load a font.
+#[derive(Debug)]
+pub struct FontError(ab_glyph::InvalidFont);
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to load the font: {}", self.0)
+    }
+}
+
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Font with the atlas of the glyphs rasterized so far.
+pub(crate) struct Font {
+    font: FontVec,
+    /// Scene texture holding the atlas.
+    pub texture_id: TextureId,
+    // sRGB-encoded RGBA pixels of the atlas: white, with the glyph coverage as alpha
+    pixels: Vec<u8>,
+    // `None` for glyphs without an outline, like spaces, and glyphs that did not fit in the atlas
+    glyphs: HashMap<char, Option<Glyph>>,
+    // top-left corner of the next glyph in the current row of the atlas, and the height of the row
+    cursor: (u32, u32),
+    row_height: u32,
+}
+
+// Cell of a glyph in the atlas, at RASTER_SIZE.
+#[derive(Clone, Copy)]
+struct Glyph {
+    // top-left corner and size of the cell in atlas pixels
+    position: Vec2,
+    size: Vec2,
+    // offset of the cell's top-left corner from the glyph origin on the baseline
+    offset: Vec2,
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Font")
+            .field("texture_id", &self.texture_id)
+            .field("glyphs", &self.glyphs.len())
+            .finish()
+    }
+}
+
+impl Font {
+    /// Load a TrueType or OpenType font whose atlas is the texture `texture_id`. The atlas starts empty.
+    pub fn new(data: Vec<u8>, texture_id: TextureId) -> Result<Self, FontError> {
+        Ok(Self {
+            font: FontVec::try_from_vec(data).map_err(FontError)?,
+            texture_id,
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE * 4) as usize],
+            glyphs: HashMap::new(),
+            cursor: (PADDING, PADDING),
+            row_height: 0,
+        })
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Rasterize the glyphs of `text` missing from the atlas. Returns true if the atlas pixels changed.
+    /// Glyphs that do not fit in the atlas anymore are left out, and drawn as nothing.
+    pub fn add_glyphs(&mut self, text: &str) -> bool {
+        let mut changed = false;
+        for c in text.chars() {
+            if c == '\n' || self.glyphs.contains_key(&c) {
+                continue;
+            }
+            let glyph = self.rasterize(c);
+            changed |= glyph.is_some();
+            self.glyphs.insert(c, glyph);
+        }
+        changed
+    }
+
+    fn rasterize(&mut self, c: char) -> Option<Glyph> {
+        let outline = self
+            .font
+            .outline_glyph(self.font.glyph_id(c).with_scale(RASTER_SIZE))?;
+        let bounds = outline.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+
+        // pack into rows from the top-left, starting a new row when the current one is full
+        if self.cursor.0 + width + PADDING > ATLAS_SIZE {
+            self.cursor = (PADDING, self.cursor.1 + self.row_height + PADDING);
+            self.row_height = 0;
+        }
+        if self.cursor.1 + height + PADDING > ATLAS_SIZE {
+            return None;
+        }
+        let (x0, y0) = self.cursor;
+        outline.draw(|x, y, coverage| {
+            let (x, y) = (x0 + x, y0 + y);
+            if x < ATLAS_SIZE && y < ATLAS_SIZE {
+                let index = ((y * ATLAS_SIZE + x) * 4) as usize;
+                let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                self.pixels[index..index + 4].copy_from_slice(&[255, 255, 255, alpha]);
+            }
+        });
+        self.cursor.0 += width + PADDING;
+        self.row_height = self.row_height.max(height);
+
+        Some(Glyph {
+            position: Vec2::new(x0 as f32, y0 as f32),
+            size: Vec2::new(width as f32, height as f32),
+            offset: Vec2::new(bounds.min.x, bounds.min.y),
+        })
+    }
+
+    /// Width and height in pixels of `text` laid out like [Self::layout]: the widest line from its origin to the
+    /// advance of its last glyph, and the lines from the ascent of the first to the descent of the last.
+    pub fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            let end = pen_positions(&font, line)
+                .last()
+                .map_or(0.0, |(_, _, end)| end);
+            width = width.max(end);
+            lines += 1;
+        }
+        let height = lines as f32 * font.height() + (lines - 1) as f32 * font.line_gap();
+        (width, height)
+    }
+
+    /// Quads of the glyphs of `text` laid out left to right from `origin`, the top-left corner of the first line:
+    /// the screen rect and the atlas region from `uv_min` to `uv_max`.
+    /// `size` is the line height from ascent to descent in pixels. Lines are broken at `\n` only.
+    pub fn layout(&self, text: &str, origin: (i32, i32), size: f32) -> Vec<(Rect, Vec2, Vec2)> {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let scale = size / RASTER_SIZE;
+        let mut quads = Vec::new();
+        let mut baseline = origin.1 as f32 + font.ascent();
+        for line in text.split('\n') {
+            for (c, pen, _) in pen_positions(&font, line) {
+                let x = origin.0 as f32 + pen;
+                if let Some(Some(glyph)) = self.glyphs.get(&c) {
+                    // snap the edges to pixels, so that neighboring glyphs keep their spacing
+                    let min = Vec2::new(x, baseline) + glyph.offset * scale;
+                    let max = min + glyph.size * scale;
+                    let (left, top) = (min.x.round() as i32, min.y.round() as i32);
+                    let (right, bottom) = (max.x.round() as i32, max.y.round() as i32);
+                    quads.push((
+                        Rect::new(left, top, right - left, bottom - top),
+                        glyph.position / ATLAS_SIZE as f32,
+                        (glyph.position + glyph.size) / ATLAS_SIZE as f32,
+                    ));
+                }
+            }
+            baseline += font.height() + font.line_gap();
+        }
+        quads
+    }
+}
+
+// Pen position of each glyph of `line` from 0, kerned against the previous glyph, and the position past its advance,
+// where the next glyph starts. Shared by [Font::measure] and [Font::layout] so that they agree.
+fn pen_positions<F: ab_glyph::Font>(
+    font: &impl ScaleFont<F>,
+    line: &str,
+) -> impl Iterator<Item = (char, f32, f32)> {
+    let mut x = 0.0;
+    let mut previous = None;
+    line.chars().map(move |c| {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            x += font.kern(previous, id);
+        }
+        let pen = x;
+        x += font.h_advance(id);
+        previous = Some(id);
+        (c, pen, x)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // any TrueType font does; the test is skipped where it is not installed
+    const FONT_PATH: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+    #[test]
+    fn measure_spans_the_laid_out_glyphs() {
+        let Ok(data) = std::fs::read(FONT_PATH) else {
+            eprintln!("skipped: no font at {FONT_PATH}");
+            return;
+        };
+        let mut font = Font::new(data, 0).unwrap();
+        let text = "Wave AV\nTo";
+        font.add_glyphs(text);
+
+        let (width, height) = font.measure(text, 20.0);
+        let (line_width, line_height) = font.measure("Wave AV", 20.0);
+        assert_eq!(width, line_width);
+        assert!(
+            height > 2.0 * line_height - 1.0,
+            "{height} for lines of {line_height}"
+        );
+
+        let quads = font.layout(text, (10, 0), 20.0);
+        assert_eq!(quads.len(), 8);
+        let right = quads
+            .iter()
+            .map(|(rect, _, _)| rect.x + rect.w)
+            .max()
+            .unwrap();
+        assert!(
+            (right - 10) as f32 <= width.ceil() + 1.0,
+            "{right} past {width}"
+        );
+        assert!((right - 10) as f32 >= width - font.measure("V", 20.0).0);
+    }
+}
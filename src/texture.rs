@@ -1,86 +1,605 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
-use image::GenericImageView;
+use image::RgbaImage;
 
 pub type TextureId = u16;
 
-#[derive(Default)]
+/// How a texture is sampled when drawn at a different size than its pixels.
+///
+/// Both filters work with the same `Filtering` sampler binding, so textures of either kind share one bind group layout
+/// and are drawn by the same pipeline. `Nearest` keeps pixel art crisp but shows jagged edges when scaled by
+/// non-integer factors; `Linear` is smooth but blurs hard edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Linear,
+    Nearest,
+}
+
+impl From<Filter> for wgpu::FilterMode {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Linear => wgpu::FilterMode::Linear,
+            Filter::Nearest => wgpu::FilterMode::Nearest,
+        }
+    }
+}
+
+/// Per-texture sampling settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureOptions {
+    pub filter: Filter,
+    /// Generate a full mipmap chain so the texture does not shimmer when drawn much smaller than its pixels.
+    /// Costs a third more memory and some CPU time on upload; small UI icons drawn at their own size can opt out.
+    pub mipmaps: bool,
+    /// Multiply the color of each texel by its alpha on upload, and blend the texture as premultiplied, so that
+    /// filtering does not bleed the color of transparent texels into the edges as dark halos.
+    /// Only changes alpha blended nodes drawn by the GPU renderer, like nodes of translucent textures.
+    ///
+    /// The color is premultiplied in linear space and encoded back to sRGB, as the sRGB texture is decoded before
+    /// filtering, so texels with low alpha keep less color precision. Default is off.
+    pub premultiplied_alpha: bool,
+    /// Maximum anisotropy of the sampler, from 1 (off) to 16, e.g. 8 to keep textures sharp when drawn squashed along
+    /// one axis. 16 is the most any device supports, so larger values are clamped to it when the texture is added, and
+    /// devices without anisotropic filtering ignore it. Needs [Filter::Linear]: adding a texture with more than 1 and
+    /// [Filter::Nearest] fails with [TextureError::AnisotropyNeedsLinear]. Needs [Self::mipmaps] to make a
+    /// difference. Default is 1.
+    pub anisotropy: u16,
+}
+
+impl TextureOptions {
+    // The options with anisotropy clamped to the range samplers accept.
+    fn validated(self) -> Result<Self, TextureError> {
+        if self.anisotropy > 1 && self.filter == Filter::Nearest {
+            return Err(TextureError::AnisotropyNeedsLinear {
+                anisotropy: self.anisotropy,
+            });
+        }
+        Ok(Self {
+            anisotropy: self.anisotropy.clamp(1, MAX_ANISOTROPY),
+            ..self
+        })
+    }
+}
+
+// Largest anisotropy clamp wgpu accepts for a sampler, on every device.
+const MAX_ANISOTROPY: u16 = 16;
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            filter: Filter::default(),
+            mipmaps: true,
+            premultiplied_alpha: false,
+            anisotropy: 1,
+        }
+    }
+}
+
+/// Failure to read or decode a texture image.
+#[derive(Debug)]
+pub enum TextureError {
+    /// The image file could not be read.
+    Io(std::io::Error),
+    /// The data is not a PNG, JPEG, WebP or BMP image.
+    UnknownFormat,
+    /// The data is corrupt, or uses a feature of its format that is not supported.
+    Decode(image::ImageError),
+    /// Raw pixel data is not `width * height * 4` bytes long.
+    InvalidLength { width: u32, height: u32, len: usize },
+    /// New pixels of an existing texture have a different size than it was created with.
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// No texture was registered with the id.
+    NotFound(TextureId),
+    /// [TextureOptions::anisotropy] is more than 1 with [Filter::Nearest], which cannot filter anisotropically.
+    AnisotropyNeedsLinear { anisotropy: u16 },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io(err) => write!(f, "failed to read texture: {err}"),
+            TextureError::UnknownFormat => {
+                write!(f, "texture data is not a PNG, JPEG, WebP or BMP image")
+            }
+            TextureError::Decode(err) => write!(f, "failed to decode texture: {err}"),
+            TextureError::InvalidLength { width, height, len } => write!(
+                f,
+                "{width}x{height} RGBA texture needs {} bytes, got {len}",
+                *width as usize * *height as usize * 4
+            ),
+            TextureError::SizeMismatch { expected, actual } => write!(
+                f,
+                "texture is {}x{}, new pixels are {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            TextureError::NotFound(id) => write!(f, "texture {id} does not exist"),
+            TextureError::AnisotropyNeedsLinear { anisotropy } => {
+                write!(f, "anisotropy {anisotropy} needs Filter::Linear")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(err) => Some(err),
+            TextureError::Decode(err) => Some(err),
+            TextureError::UnknownFormat
+            | TextureError::InvalidLength { .. }
+            | TextureError::SizeMismatch { .. }
+            | TextureError::NotFound(_)
+            | TextureError::AnisotropyNeedsLinear { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(err: std::io::Error) -> Self {
+        TextureError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Decode(err)
+    }
+}
+
+/// Texture data waiting to be uploaded, kept for exporters. Ids are allocated by the scene.
+#[derive(Debug, Default)]
 pub(crate) struct TextureInfoManager<'a> {
-    last_id: TextureId,
     texture_infos: HashMap<TextureId, TextureInfo<'a>>,
 }
 
 impl<'a> TextureInfoManager<'a> {
-    pub fn add_texture_info(&mut self, data: &'a [u8]) -> TextureId {
-        let id = self.last_id;
-        let texture_info = TextureInfo { data };
-        self.last_id += 1;
+    pub fn insert(&mut self, id: TextureId, texture_info: TextureInfo<'a>) {
         self.texture_infos.entry(id).insert_entry(texture_info);
+    }
+
+    pub fn get(&self, id: TextureId) -> Option<&TextureInfo<'a>> {
+        self.texture_infos.get(&id)
+    }
 
-        id
+    pub fn iter(&self) -> impl Iterator<Item = (&TextureId, &TextureInfo<'a>)> {
+        self.texture_infos.iter()
     }
 }
 
+#[derive(Debug)]
+pub(crate) enum TextureData<'a> {
+    /// Encoded image. Borrowed when embedded in the binary, owned when read from a file.
+    Encoded(Cow<'a, [u8]>),
+    /// Tightly packed sRGB-encoded RGBA pixels, row by row.
+    Rgba8 {
+        width: u32,
+        height: u32,
+        pixels: Cow<'a, [u8]>,
+    },
+}
+
+#[derive(Debug)]
 pub(crate) struct TextureInfo<'a> {
-    data: &'a [u8],
+    pub data: TextureData<'a>,
+    pub options: TextureOptions,
+}
+
+impl<'a> TextureInfo<'a> {
+    /// Encoded image, decoded when the textures are loaded to the GPU.
+    pub fn encoded(data: impl Into<Cow<'a, [u8]>>) -> Self {
+        Self {
+            data: TextureData::Encoded(data.into()),
+            options: TextureOptions::default(),
+        }
+    }
+
+    /// Like [Self::encoded], but fails early on data that is not a supported image.
+    /// Only the header is checked here; decoding is deferred until the textures are loaded to the GPU.
+    pub fn try_encoded(data: impl Into<Cow<'a, [u8]>>) -> Result<Self, TextureError> {
+        let data = data.into();
+        let format = guess_format(&data)?;
+        image::ImageReader::with_format(std::io::Cursor::new(&data), format).into_dimensions()?;
+        Ok(Self::encoded(data))
+    }
+
+    /// Raw pixels, uploaded without going through the image decoder.
+    pub fn rgba8(
+        width: u32,
+        height: u32,
+        pixels: impl Into<Cow<'a, [u8]>>,
+    ) -> Result<Self, TextureError> {
+        let pixels = pixels.into();
+        check_rgba8_len(width, height, &pixels)?;
+        Ok(Self {
+            data: TextureData::Rgba8 {
+                width,
+                height,
+                pixels,
+            },
+            options: TextureOptions::default(),
+        })
+    }
+
+    /// Sample the texture with `options`, clamped to what the sampler accepts.
+    /// Fails if the options cannot be combined, see [TextureOptions::anisotropy].
+    pub fn with_options(self, options: TextureOptions) -> Result<Self, TextureError> {
+        Ok(Self {
+            options: options.validated()?,
+            ..self
+        })
+    }
+
+    /// Encoded bytes as given, if the texture was not registered as raw pixels.
+    pub fn encoded_data(&self) -> Option<&[u8]> {
+        match &self.data {
+            TextureData::Encoded(data) => Some(data),
+            TextureData::Rgba8 { .. } => None,
+        }
+    }
+
+    /// Format detected from the header of an encoded image, `None` for raw pixels and unrecognized data.
+    pub fn format(&self) -> Option<image::ImageFormat> {
+        match &self.data {
+            TextureData::Encoded(data) => guess_format(data).ok(),
+            TextureData::Rgba8 { .. } => None,
+        }
+    }
+
+    /// Width and height in pixels. Encoded images only have their header read.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        match &self.data {
+            TextureData::Encoded(data) => image::ImageReader::new(std::io::Cursor::new(data))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok(),
+            TextureData::Rgba8 { width, height, .. } => Some((*width, *height)),
+        }
+    }
+
+    /// Decoded pixels.
+    pub fn to_rgba8(&self) -> Result<RgbaImage, TextureError> {
+        match &self.data {
+            TextureData::Encoded(data) => decode(data),
+            TextureData::Rgba8 {
+                width,
+                height,
+                pixels,
+            } => RgbaImage::from_raw(*width, *height, pixels.to_vec()).ok_or(
+                TextureError::InvalidLength {
+                    width: *width,
+                    height: *height,
+                    len: pixels.len(),
+                },
+            ),
+        }
+    }
+}
+
+impl TextureInfo<'static> {
+    /// Read encoded image file. Only the header is checked here; decoding is deferred until the textures are loaded to the GPU.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        Self::try_encoded(std::fs::read(path)?)
+    }
+}
+
+fn has_translucent_texel(pixels: &[u8]) -> bool {
+    pixels.chunks_exact(4).any(|pixel| pixel[3] < 255)
+}
+
+// Format of an encoded image, among those the image crate is built with.
+fn guess_format(data: &[u8]) -> Result<image::ImageFormat, TextureError> {
+    image::guess_format(data)
+        .ok()
+        .filter(|format| format.reading_enabled())
+        .ok_or(TextureError::UnknownFormat)
+}
+
+// Decode an encoded image to sRGB-encoded RGBA pixels.
+fn decode(data: &[u8]) -> Result<RgbaImage, TextureError> {
+    let format = guess_format(data)?;
+    Ok(image::load_from_memory_with_format(data, format)?.to_rgba8())
+}
+
+fn check_rgba8_len(width: u32, height: u32, pixels: &[u8]) -> Result<(), TextureError> {
+    if pixels.len() as u64 != width as u64 * height as u64 * 4 {
+        return Err(TextureError::InvalidLength {
+            width,
+            height,
+            len: pixels.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Texture data registered with the builder or the scene.
+/// The scene comes first, as it also holds the latest pixels of updated textures.
+pub(crate) fn lookup<'s>(
+    scene: &'s crate::Scene,
+    texture_infos: Option<&'s TextureInfoManager>,
+    id: TextureId,
+) -> Option<&'s TextureInfo<'s>> {
+    scene
+        .texture_infos
+        .get(id)
+        .or_else(|| texture_infos.and_then(|infos| infos.get(id)))
 }
 
 pub(crate) struct TextureManager {
     pub textures: HashMap<TextureId, Texture>,
     pub bind_group_layout: wgpu::BindGroupLayout,
+    /// Layout of [TextureArray::bind_group].
+    pub array_bind_group_layout: wgpu::BindGroupLayout,
+    // every texture copied into one array, if they all have the same size and sampling settings
+    array: Option<TextureArray>,
+    // textures were added since the array was built
+    array_outdated: bool,
+    // textures whose pixels changed since they were copied into the array
+    stale_layers: Vec<TextureId>,
+    /// Targets the opacity groups are drawn into, by group index. Not part of the array.
+    pub group_targets: Vec<Texture>,
+}
+
+/// Every texture in one array texture, so that textured quads can be drawn with a single bind group.
+pub(crate) struct TextureArray {
+    pub bind_group: wgpu::BindGroup,
+    texture: wgpu::Texture,
+    /// Array layer of each texture.
+    pub layers: HashMap<TextureId, u32>,
 }
 
 impl TextureManager {
     pub fn new(device: &wgpu::Device) -> Self {
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
+        let layout = |view_dimension| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            })
+        };
+
+        Self {
+            textures: HashMap::new(),
+            bind_group_layout: layout(wgpu::TextureViewDimension::D2),
+            array_bind_group_layout: layout(wgpu::TextureViewDimension::D2Array),
+            array: None,
+            array_outdated: false,
+            stale_layers: Vec::new(),
+            group_targets: Vec::new(),
+        }
+    }
+
+    /// Texture array holding every texture, `None` if they differ in size or sampling settings,
+    /// or if there are fewer than two.
+    pub fn array(&self) -> Option<&TextureArray> {
+        self.array.as_ref()
+    }
+
+    /// Bring the texture array up to date with the textures loaded or updated since the last call.
+    /// Returns true if the array was rebuilt, which moves textures to other layers.
+    pub fn prepare_array(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        if self.array_outdated {
+            self.array = self.build_array(device, queue);
+            self.array_outdated = false;
+            self.stale_layers.clear();
+            return true;
+        }
+        if let Some(array) = &self.array
+            && !self.stale_layers.is_empty()
+        {
+            let layers = self
+                .stale_layers
+                .iter()
+                .filter_map(|id| Some((self.textures.get(id)?, *array.layers.get(id)?)));
+            copy_to_layers(device, queue, &array.texture, layers);
+        }
+        self.stale_layers.clear();
+        false
+    }
+
+    fn build_array(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<TextureArray> {
+        let mut textures: Vec<(&TextureId, &Texture)> = self.textures.iter().collect();
+        textures.sort_by_key(|(id, _)| **id);
+        let (_, first) = *textures.first()?;
+        let key = |texture: &Texture| {
+            (
+                texture.texture.size(),
+                texture.texture.mip_level_count(),
+                texture.options.filter,
+                texture.options.anisotropy,
+            )
+        };
+        if textures.len() < 2
+            || textures.len() as u32 > device.limits().max_texture_array_layers
+            || textures
+                .iter()
+                .any(|(_, texture)| key(texture) != key(first))
+        {
+            return None;
+        }
+
+        let size = wgpu::Extent3d {
+            depth_or_array_layers: textures.len() as u32,
+            ..first.texture.size()
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture array"),
+            size,
+            mip_level_count: first.texture.mip_level_count(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let layers: HashMap<TextureId, u32> = textures
+            .iter()
+            .enumerate()
+            .map(|(layer, (id, _))| (**id, layer as u32))
+            .collect();
+        copy_to_layers(
+            device,
+            queue,
+            &texture,
+            textures.iter().map(|(id, texture)| (*texture, layers[*id])),
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = create_sampler(device, first.options);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Array Bind Group"),
+            layout: &self.array_bind_group_layout,
             entries: &[
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
+                    resource: wgpu::BindingResource::TextureView(&view),
                 },
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
                 },
             ],
         });
-
-        Self {
-            textures: HashMap::new(),
-            bind_group_layout,
-        }
+        Some(TextureArray {
+            bind_group,
+            texture,
+            layers,
+        })
     }
 
+    /// Upload every texture. Textures that fail to decode are skipped and returned with their id;
+    /// texture nodes using them draw nothing.
     pub fn load(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         texture_infos: &TextureInfoManager,
-    ) {
-        for (texture_id, texture_info) in &texture_infos.texture_infos {
-            let texture =
-                Texture::from_bytes(device, queue, texture_info.data, &self.bind_group_layout);
-            self.textures.entry(*texture_id).insert_entry(texture);
+    ) -> Vec<(TextureId, TextureError)> {
+        let mut errors = Vec::new();
+        for (texture_id, texture_info) in texture_infos.iter() {
+            if let Err(err) = self.load_one(device, queue, *texture_id, texture_info) {
+                errors.push((*texture_id, err));
+            }
         }
+        errors
+    }
+
+    pub fn load_one(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture_id: TextureId,
+        texture_info: &TextureInfo,
+    ) -> Result<(), TextureError> {
+        let texture = match &texture_info.data {
+            TextureData::Encoded(data) => Texture::from_bytes(
+                device,
+                queue,
+                data,
+                texture_info.options,
+                &self.bind_group_layout,
+            )?,
+            TextureData::Rgba8 {
+                width,
+                height,
+                pixels,
+            } => Texture::from_rgba8(
+                device,
+                queue,
+                *width,
+                *height,
+                pixels,
+                texture_info.options,
+                &self.bind_group_layout,
+            )?,
+        };
+        self.textures.entry(texture_id).insert_entry(texture);
+        self.array_outdated = true;
+        Ok(())
+    }
+
+    /// Replace the pixels of an uploaded texture, reusing its GPU allocation.
+    /// Returns whether the texture went from opaque to translucent or back, which changes how it is drawn.
+    pub fn update_texture(
+        &mut self,
+        queue: &wgpu::Queue,
+        texture_id: TextureId,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<bool, TextureError> {
+        let texture = self
+            .textures
+            .get_mut(&texture_id)
+            .ok_or(TextureError::NotFound(texture_id))?;
+        let translucent = texture.translucent;
+        texture.update_rgba8(queue, width, height, pixels)?;
+        let translucency_changed = texture.translucent != translucent;
+        self.stale_layers.push(texture_id);
+        Ok(translucency_changed)
+    }
+
+    /// Width and height in pixels of an uploaded texture.
+    pub fn texture_size(&self, id: TextureId) -> Option<(u32, u32)> {
+        self.get_texture(id)
+            .map(|texture| (texture.width, texture.height))
     }
 
     pub fn get_texture(&self, id: TextureId) -> Option<&Texture> {
         self.textures.get(&id)
     }
+
+    /// Make the target of every opacity group up to `count` exist with the size `width` x `height`.
+    /// Targets of another size are replaced, and unused ones are dropped.
+    pub fn prepare_group_targets(
+        &mut self,
+        device: &wgpu::Device,
+        count: usize,
+        (width, height): (u32, u32),
+        format: wgpu::TextureFormat,
+    ) {
+        self.group_targets
+            .retain(|target| (target.width, target.height) == (width, height));
+        self.group_targets.truncate(count);
+        while self.group_targets.len() < count {
+            let target = Texture::target(device, width, height, format, &self.bind_group_layout);
+            self.group_targets.push(target);
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct Texture {
     pub bind_group: Option<wgpu::BindGroup>,
+    texture: wgpu::Texture,
+    pub options: TextureOptions,
+    pub width: u32,
+    pub height: u32,
+    /// Whether any texel is not fully opaque, found by scanning the pixels on upload.
+    pub translucent: bool,
 }
 
 impl Texture {
@@ -88,55 +607,60 @@ impl Texture {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         data: &[u8],
+        options: TextureOptions,
         bind_group_layout: &wgpu::BindGroupLayout,
-    ) -> Self {
-        let image = image::load_from_memory(data).unwrap();
-        let rgba8 = image.to_rgba8();
+    ) -> Result<Self, TextureError> {
+        let rgba8 = decode(data)?;
+        let (width, height) = rgba8.dimensions();
+        Self::from_rgba8(
+            device,
+            queue,
+            width,
+            height,
+            &rgba8,
+            options,
+            bind_group_layout,
+        )
+    }
 
-        let dimensions = image.dimensions();
+    /// Upload tightly packed sRGB-encoded RGBA pixels as they are, without the image decoder.
+    pub fn from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        options: TextureOptions,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self, TextureError> {
+        check_rgba8_len(width, height, pixels)?;
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size,
-            mip_level_count: 1,
+            mip_level_count: if options.mipmaps {
+                size.max_mips(wgpu::TextureDimension::D2)
+            } else {
+                1
+            },
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            // copied into the texture array
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytemuck::cast_slice(&rgba8),
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        write_rgba8(queue, &texture, pixels, options.premultiplied_alpha);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = create_sampler(device, options);
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Texture Bind Group"),
@@ -153,31 +677,304 @@ impl Texture {
             ],
         });
 
+        Ok(Self {
+            bind_group: Some(bind_group),
+            texture,
+            options,
+            width,
+            height,
+            translucent: has_translucent_texel(pixels),
+        })
+    }
+
+    /// Texture drawn into and then sampled, like the target of an opacity group. It is sampled pixel for pixel,
+    /// and has premultiplied alpha as blending leaves it.
+    pub fn target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("group target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let options = TextureOptions {
+            filter: Filter::Nearest,
+            mipmaps: false,
+            premultiplied_alpha: true,
+            anisotropy: 1,
+        };
+        let sampler = create_sampler(device, options);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Group Target Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
         Self {
             bind_group: Some(bind_group),
+            texture,
+            options,
+            width,
+            height,
+            translucent: true,
+        }
+    }
+
+    pub fn view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Replace the pixels, reusing the GPU allocation. The size must stay the same.
+    pub fn update_rgba8(
+        &mut self,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), TextureError> {
+        if (width, height) != (self.width, self.height) {
+            return Err(TextureError::SizeMismatch {
+                expected: (self.width, self.height),
+                actual: (width, height),
+            });
+        }
+        check_rgba8_len(width, height, pixels)?;
+        write_rgba8(
+            queue,
+            &self.texture,
+            pixels,
+            self.options.premultiplied_alpha,
+        );
+        self.translucent = has_translucent_texel(pixels);
+        Ok(())
+    }
+}
+
+// The anisotropy of `options` was validated when the texture was added, see [TextureInfo::with_options].
+fn create_sampler(device: &wgpu::Device, options: TextureOptions) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: None,
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: options.filter.into(),
+        min_filter: options.filter.into(),
+        mipmap_filter: wgpu::MipmapFilterMode::Linear,
+        anisotropy_clamp: options.anisotropy,
+        ..Default::default()
+    })
+}
+
+// Copy every mip level of the textures into their layers of the array texture.
+fn copy_to_layers<'t>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    array: &wgpu::Texture,
+    layers: impl Iterator<Item = (&'t Texture, u32)>,
+) {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    for (texture, layer) in layers {
+        for mip_level in 0..texture.texture.mip_level_count() {
+            encoder.copy_texture_to_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture.texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyTextureInfo {
+                    texture: array,
+                    mip_level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                texture
+                    .texture
+                    .size()
+                    .mip_level_size(mip_level, wgpu::TextureDimension::D2),
+            );
+        }
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+// Write the pixels to the first mip level and their downsampled copies to the others.
+// Premultiplied pixels are downsampled as they are, which weighs each color by its alpha.
+fn write_rgba8(queue: &wgpu::Queue, texture: &wgpu::Texture, pixels: &[u8], premultiply: bool) {
+    let mut level = if premultiply {
+        Cow::Owned(premultiplied(pixels))
+    } else {
+        Cow::Borrowed(pixels)
+    };
+    let mut size = texture.size();
+    for mip_level in 0..texture.mip_level_count() {
+        if mip_level > 0 {
+            let (downsampled, next_size) = downsample(&level, size);
+            level = Cow::Owned(downsampled);
+            size = next_size;
+        }
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &level,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+}
+
+// Halve the size with a 2x2 box filter. Odd edges repeat their last row or column.
+// Color channels are averaged in linear space, as the sampler filters sRGB textures.
+fn downsample(pixels: &[u8], size: wgpu::Extent3d) -> (Vec<u8>, wgpu::Extent3d) {
+    let next_size = wgpu::Extent3d {
+        width: (size.width / 2).max(1),
+        height: (size.height / 2).max(1),
+        depth_or_array_layers: 1,
+    };
+    let pixel = |x: u32, y: u32| {
+        let x = x.min(size.width - 1);
+        let y = y.min(size.height - 1);
+        let start = ((y * size.width + x) * 4) as usize;
+        &pixels[start..start + 4]
+    };
+
+    let mut downsampled = Vec::with_capacity((next_size.width * next_size.height * 4) as usize);
+    for y in 0..next_size.height {
+        for x in 0..next_size.width {
+            let samples = [
+                pixel(2 * x, 2 * y),
+                pixel(2 * x + 1, 2 * y),
+                pixel(2 * x, 2 * y + 1),
+                pixel(2 * x + 1, 2 * y + 1),
+            ];
+            for channel in 0..3 {
+                let linear = samples
+                    .iter()
+                    .map(|sample| srgb_to_linear(sample[channel]))
+                    .sum::<f32>()
+                    / 4.0;
+                downsampled.push(linear_to_srgb(linear));
+            }
+            let alpha = samples.iter().map(|sample| sample[3] as u32).sum::<u32>();
+            downsampled.push(((alpha + 2) / 4) as u8);
         }
     }
+    (downsampled, next_size)
 }
 
+// Multiply the color channels by alpha in linear space.
+fn premultiplied(pixels: &[u8]) -> Vec<u8> {
+    let mut premultiplied = pixels.to_vec();
+    for pixel in premultiplied.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in &mut pixel[..3] {
+            *channel = linear_to_srgb(srgb_to_linear(*channel) * alpha);
+        }
+    }
+    premultiplied
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    (crate::draw::srgb_encode(c) * 255.0).round() as u8
+}
+
+/// Depth buffer of `format` with `sample_count` samples per pixel, matching the color target.
 pub(crate) fn create_depth_texture(
     device: &wgpu::Device,
-    configuration: &wgpu::SurfaceConfiguration,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    format: wgpu::TextureFormat,
 ) -> wgpu::TextureView {
     let size = wgpu::Extent3d {
-        width: configuration.width,
-        height: configuration.height,
+        width,
+        height,
         depth_or_array_layers: 1,
     };
     let texture = device.create_texture(&wgpu::TextureDescriptor {
         label: None,
         size,
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth32Float,
+        format,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        view_formats: &[wgpu::TextureFormat::Depth32Float],
+        view_formats: &[format],
     });
 
     texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_is_clamped_when_added() {
+        let with_anisotropy = |filter, anisotropy| {
+            TextureInfo::encoded(&[][..]).with_options(TextureOptions {
+                filter,
+                anisotropy,
+                ..Default::default()
+            })
+        };
+        let anisotropy = |filter, anisotropy| {
+            with_anisotropy(filter, anisotropy)
+                .unwrap()
+                .options
+                .anisotropy
+        };
+        assert_eq!(anisotropy(Filter::Linear, 0), 1);
+        assert_eq!(anisotropy(Filter::Linear, 8), 8);
+        assert_eq!(anisotropy(Filter::Linear, 40), 16);
+        assert_eq!(anisotropy(Filter::Nearest, 1), 1);
+        assert!(matches!(
+            with_anisotropy(Filter::Nearest, 4),
+            Err(TextureError::AnisotropyNeedsLinear { anisotropy: 4 })
+        ));
+    }
+}
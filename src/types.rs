@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct Rect {
     pub x: i32,
     pub y: i32,
@@ -11,6 +11,19 @@ impl Rect {
         Self { x, y, w, h }
     }
 
+    /// Overlapping area of the two rects. Width and height are 0 if they do not overlap.
+    pub fn intersect(self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.w).min(other.x + other.w);
+        let bottom = (self.y + self.h).min(other.y + other.h);
+        Rect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+    }
+
+    pub fn contains(self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+
     pub fn dimension(self) -> Dimension {
         Dimension {
             width: self.w,
@@ -19,14 +32,35 @@ impl Rect {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub(crate) struct Dimension {
+/// Pixel rectangle on the screen, from the top-left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoundingBox {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl From<Rect> for BoundingBox {
+    fn from(rect: Rect) -> Self {
+        Self {
+            x: rect.x,
+            y: rect.y,
+            w: rect.w,
+            h: rect.h,
+        }
+    }
+}
+
+/// Width and height in pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimension {
     pub width: i32,
     pub height: i32,
 }
 
 impl Dimension {
-    pub fn new(width: i32, height: i32) -> Self {
+    pub const fn new(width: i32, height: i32) -> Self {
         Self { width, height }
     }
 }
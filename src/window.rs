@@ -0,0 +1,82 @@
+/// Initial window settings, set with [crate::Guiug::set_window_config].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowConfig {
+    /// Initial width in pixel.
+    pub width: u32,
+    /// Initial height in pixel.
+    pub height: u32,
+    /// Whether the user can resize the window.
+    pub resizable: bool,
+    /// Smallest (width, height) the window can be resized to.
+    pub min_size: Option<(u32, u32)>,
+    /// Start maximized instead of at `width` x `height`.
+    pub maximized: bool,
+    /// How finished frames are shown on the screen.
+    pub present_mode: PresentMode,
+}
+
+/// Frame presentation strategy, trading battery life against input latency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PresentMode {
+    /// Wait for vertical blank. No tearing, lowest power use. Supported everywhere.
+    #[default]
+    Fifo,
+    /// Like Fifo, but a late frame is shown immediately and may tear.
+    FifoRelaxed,
+    /// Replace the queued frame with the newest one. No tearing, low latency.
+    Mailbox,
+    /// Show frames as soon as they are ready. Lowest latency, may tear.
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+impl PresentMode {
+    /// The requested mode if the surface supports it, otherwise Fifo, which every surface supports.
+    pub(crate) fn select(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let mode = self.into();
+        if supported.contains(&mode) {
+            mode
+        } else {
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 800,
+            resizable: true,
+            min_size: None,
+            maximized: false,
+            present_mode: PresentMode::default(),
+        }
+    }
+}
+
+impl WindowConfig {
+    pub(crate) fn attributes(&self, title: &str) -> winit::window::WindowAttributes {
+        let mut attributes = winit::window::Window::default_attributes()
+            .with_inner_size(winit::dpi::PhysicalSize::new(self.width, self.height))
+            .with_resizable(self.resizable)
+            .with_maximized(self.maximized)
+            .with_title(title)
+            .with_visible(false);
+        if let Some((width, height)) = self.min_size {
+            attributes =
+                attributes.with_min_inner_size(winit::dpi::PhysicalSize::new(width, height));
+        }
+        attributes
+    }
+}